@@ -1,3 +1,4 @@
+use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::time::Instant;
@@ -7,12 +8,11 @@ use atlas_packer::texture::PolygonMappedTexture;
 use rayon::prelude::*;
 
 use atlas_packer::{
-    export::JpegAtlasExporter,
     pack::AtlasPacker,
     place::{GuillotineTexturePlacer, TexturePlacerConfig},
     texture::{
         cache::{TextureCache, TextureSizeCache},
-        DownsampleFactor,
+        DownsampleFactor, TextureSource,
     },
 };
 
@@ -49,7 +49,7 @@ fn main() {
                 id: format!("texture_{}_{}", i, j),
                 uv_coords,
                 texture_uri: image_path,
-                downsample_factor: DownsampleFactor::new(&downsample_factor),
+                downsample_factor: DownsampleFactor::new(&downsample_factor).unwrap(),
             });
         }
     }
@@ -59,6 +59,7 @@ fn main() {
         width: 4096,
         height: 4096,
         padding: 0,
+        ..Default::default()
     };
 
     let packer = Mutex::new(AtlasPacker::default());
@@ -70,9 +71,10 @@ fn main() {
     // place textures on the atlas
     polygons.par_iter().for_each(|polygon| {
         let place_start = Instant::now();
-        let texture_size = texture_size_cache.get_or_insert(&polygon.texture_uri);
+        let source = TextureSource::path(&polygon.texture_uri);
+        let texture_size = texture_size_cache.get_or_insert(&source).unwrap();
         let cropped_texture = PolygonMappedTexture::new(
-            &polygon.texture_uri,
+            source,
             texture_size,
             &polygon.uv_coords,
             polygon.downsample_factor.clone(),
@@ -87,7 +89,9 @@ fn main() {
     });
 
     let packer = packer.into_inner().unwrap();
-    let packed = packer.pack(GuillotineTexturePlacer::new(config.clone()));
+    let packed = packer
+        .pack(GuillotineTexturePlacer::new(config.clone()))
+        .unwrap();
 
     let duration = packing_start.elapsed();
     println!("all packing process {:?}", duration);
@@ -97,16 +101,16 @@ fn main() {
     // Caches the original textures for exporting to an atlas.
     let texture_cache = TextureCache::new(100_000_000);
     let output_dir = Path::new("./examples/output/");
-    packed.export(
-        WebpAtlasExporter::default(),
-        output_dir,
-        &texture_cache,
-        config.width(),
-        config.height(),
-    );
+    packed
+        .export(&WebpAtlasExporter::default(), output_dir, &texture_cache)
+        .unwrap();
     let duration = start.elapsed();
     println!("all atlas export process {:?}", duration);
 
+    // Layout of the atlases, for tools that do not run the packer
+    let manifest = File::create(output_dir.join("manifest.json")).unwrap();
+    packed.write_manifest("webp", manifest).unwrap();
+
     let duration = all_process_start.elapsed();
     println!("all process {:?}", duration);
 }
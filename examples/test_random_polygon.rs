@@ -11,7 +11,7 @@ use atlas_packer::{
     place::{GuillotineTexturePlacer, TexturePlacerConfig},
     texture::{
         cache::{TextureCache, TextureSizeCache},
-        DownsampleFactor,
+        DownsampleFactor, TextureSource,
     },
 };
 
@@ -85,7 +85,7 @@ fn main() {
                 id: format!("texture_{}_{}", i, j),
                 uv_coords,
                 texture_uri: image_path,
-                downsample_factor: DownsampleFactor::new(&downsample_factor),
+                downsample_factor: DownsampleFactor::new(&downsample_factor).unwrap(),
             });
         }
     }
@@ -95,6 +95,7 @@ fn main() {
         width: 4096,
         height: 4096,
         padding: 0,
+        ..Default::default()
     };
 
     let packer = Mutex::new(AtlasPacker::default());
@@ -103,9 +104,10 @@ fn main() {
     let texture_size_cache = TextureSizeCache::new();
     // place textures on the atlas
     polygons.par_iter().for_each(|polygon| {
-        let texture_size = texture_size_cache.get_or_insert(&polygon.texture_uri);
+        let source = TextureSource::path(&polygon.texture_uri);
+        let texture_size = texture_size_cache.get_or_insert(&source).unwrap();
         let cropped_texture = PolygonMappedTexture::new(
-            &polygon.texture_uri,
+            source,
             texture_size,
             &polygon.uv_coords,
             polygon.downsample_factor.clone(),
@@ -118,19 +120,17 @@ fn main() {
     });
 
     let packer = packer.into_inner().unwrap();
-    let packed = packer.pack(GuillotineTexturePlacer::new(config.clone()));
+    let packed = packer
+        .pack(GuillotineTexturePlacer::new(config.clone()))
+        .unwrap();
 
     // Caches the original textures for exporting to an atlas.
     let texture_cache = TextureCache::new(100_000_000);
     let output_dir = Path::new("./examples/output/");
 
-    packed.export(
-        JpegAtlasExporter::default(),
-        output_dir,
-        &texture_cache,
-        config.width(),
-        config.height(),
-    );
+    packed
+        .export(&JpegAtlasExporter::default(), output_dir, &texture_cache)
+        .unwrap();
     let mut count = 0;
     let count_limit = 20;
     polygons.iter().for_each(|polygon| {
@@ -150,10 +150,10 @@ fn main() {
 
             // crop image
             let cropped_image = image.crop(
-                tex_bbox.0 as u32,
-                tex_bbox.1 as u32,
-                (tex_bbox.2 - tex_bbox.0) as u32,
-                (tex_bbox.3 - tex_bbox.1) as u32,
+                tex_bbox.0,
+                tex_bbox.1,
+                tex_bbox.2 - tex_bbox.0,
+                tex_bbox.3 - tex_bbox.1,
             );
 
             // save image
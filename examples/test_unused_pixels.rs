@@ -17,16 +17,14 @@ fn main() {
     let config = TexturePlacerConfig::new(500, 500, 1);
 
     let packer = AtlasPacker::default();
-    let packed = packer.pack(GuillotineTexturePlacer::new(config.clone()));
+    let packed = packer
+        .pack(GuillotineTexturePlacer::new(config.clone()))
+        .unwrap();
 
     let output_dir = Path::new("examples/output/");
-    packed.export(
-        PngAtlasExporter::default(),
-        output_dir,
-        &texture_cache,
-        config.width(),
-        config.height(),
-    );
+    packed
+        .export(&PngAtlasExporter::default(), output_dir, &texture_cache)
+        .unwrap();
 
     let (all_pixels, unused_pixels) = unused_pixels::unused_pixels();
 
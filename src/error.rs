@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::texture::TextureSourceId;
+use crate::{AtlasID, ClusterID};
+
+#[derive(Debug, Error)]
+pub enum AtlasPackerError {
+    #[error("image file not found: {}", .path.display())]
+    ImageNotFound { path: PathBuf },
+    #[error("failed to decode image {texture}: {source}")]
+    ImageDecode {
+        texture: TextureSourceId,
+        #[source]
+        source: image::ImageError,
+    },
+    #[error("downsample factor must be between 0 and 1, got {0}")]
+    InvalidDownsampleFactor(f32),
+    #[error("texture of cluster {cluster_id} ({width}x{height}) does not fit in the atlas")]
+    TextureTooLarge {
+        cluster_id: ClusterID,
+        width: u32,
+        height: u32,
+    },
+    #[error("texture of cluster {0} could not be placed")]
+    PlacementFailed(ClusterID),
+    #[error("image data of texture source {0} is not attached")]
+    SourceDetached(TextureSourceId),
+    #[error("atlas {0} does not exist")]
+    AtlasNotFound(AtlasID),
+    #[error("cluster {0} has no texture")]
+    ClusterNotFound(ClusterID),
+    #[error("texture cache failed: {0}")]
+    Cache(#[from] stretto::CacheError),
+    #[error("failed to encode atlas: {0}")]
+    Encode(String),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl AtlasPackerError {
+    // Tell a missing file apart from a broken one
+    pub(crate) fn from_image_error(path: &Path, error: image::ImageError) -> Self {
+        match error {
+            image::ImageError::IoError(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                AtlasPackerError::ImageNotFound {
+                    path: path.to_path_buf(),
+                }
+            }
+            source => AtlasPackerError::ImageDecode {
+                texture: path.to_string_lossy().into_owned().into(),
+                source,
+            },
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, AtlasPackerError>;
@@ -0,0 +1,155 @@
+use hashbrown::HashMap;
+use image::{ImageFormat, RgbaImage};
+use ravif::{AlphaColorMode, Img, RGBA8};
+
+use super::{create_atlas_rgba, AtlasExporter, CompositeOptions};
+use crate::{
+    error::{AtlasPackerError, Result},
+    place::PlacedTextureGeometry,
+    texture::{cache::TextureCache, ClusterBoundingTexture},
+    ClusterID,
+};
+
+#[derive(Clone)]
+pub struct AvifAtlasExporter {
+    pub ext: String,
+    pub composite: CompositeOptions,
+    /// Quality of the color channels, in 1-100
+    pub quality: f32,
+    /// Quality of the alpha channel, in 1-100
+    pub alpha_quality: f32,
+    /// Encoding speed, in 1-10 (1 = slowest and smallest)
+    pub speed: u8,
+    /// Store the colors premultiplied by alpha, which drops the colors of transparent pixels.
+    /// Not every decoder supports it.
+    pub premultiplied_alpha: bool,
+}
+
+impl Default for AvifAtlasExporter {
+    fn default() -> Self {
+        AvifAtlasExporter {
+            ext: "avif".to_string(),
+            composite: CompositeOptions::default(),
+            quality: 80.0,
+            alpha_quality: 80.0,
+            speed: 4,
+            premultiplied_alpha: false,
+        }
+    }
+}
+
+impl AtlasExporter for AvifAtlasExporter {
+    fn get_extension(&self) -> &str {
+        &self.ext
+    }
+
+    fn get_image_format(&self) -> Option<ImageFormat> {
+        Some(ImageFormat::Avif)
+    }
+
+    fn encode_atlas(
+        &self,
+        atlas_data: &[PlacedTextureGeometry],
+        textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+        texture_cache: &TextureCache,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>> {
+        let atlas_image = create_atlas_rgba(
+            atlas_data,
+            textures,
+            texture_cache,
+            width,
+            height,
+            &self.composite,
+        )?;
+        self.encode(&atlas_image)
+    }
+}
+
+impl AvifAtlasExporter {
+    /// Encode the image into an AVIF file
+    pub fn encode(&self, image: &RgbaImage) -> Result<Vec<u8>> {
+        // The encoder panics on out-of-range settings
+        if !(1.0..=100.0).contains(&self.quality) {
+            return Err(AtlasPackerError::Encode(format!(
+                "AVIF quality must be in 1-100, got {}",
+                self.quality
+            )));
+        }
+        if !(1.0..=100.0).contains(&self.alpha_quality) {
+            return Err(AtlasPackerError::Encode(format!(
+                "AVIF alpha quality must be in 1-100, got {}",
+                self.alpha_quality
+            )));
+        }
+        if !(1..=10).contains(&self.speed) {
+            return Err(AtlasPackerError::Encode(format!(
+                "AVIF speed must be in 1-10, got {}",
+                self.speed
+            )));
+        }
+
+        let alpha_color_mode = if self.premultiplied_alpha {
+            AlphaColorMode::Premultiplied
+        } else {
+            AlphaColorMode::UnassociatedClean
+        };
+        let pixels: Vec<RGBA8> = image
+            .pixels()
+            .map(|p| RGBA8::new(p[0], p[1], p[2], p[3]))
+            .collect();
+
+        let encoded = ravif::Encoder::new()
+            .with_quality(self.quality)
+            .with_alpha_quality(self.alpha_quality)
+            .with_speed(self.speed)
+            .with_alpha_color_mode(alpha_color_mode)
+            .encode_rgba(Img::new(
+                pixels.as_slice(),
+                image.width() as usize,
+                image.height() as usize,
+            ))
+            .map_err(|e| AtlasPackerError::Encode(e.to_string()))?;
+        Ok(encoded.avif_file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn test_avif_options() {
+        let image = RgbaImage::from_fn(16, 16, |x, y| {
+            Rgba([
+                (x * 16) as u8,
+                (y * 16) as u8,
+                128,
+                if x < 8 { 255 } else { 0 },
+            ])
+        });
+
+        let exporter = AvifAtlasExporter {
+            speed: 10,
+            ..Default::default()
+        };
+        let avif = exporter.encode(&image).unwrap();
+        // ISOBMFF `ftyp` box with the `avif` major brand
+        assert_eq!(&avif[4..12], b"ftypavif");
+
+        let exporter = AvifAtlasExporter {
+            quality: 0.0,
+            ..Default::default()
+        };
+        assert!(exporter.encode(&image).is_err());
+
+        let exporter = AvifAtlasExporter {
+            speed: 11,
+            ..Default::default()
+        };
+        assert!(exporter.encode(&image).is_err());
+    }
+}
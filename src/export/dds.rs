@@ -0,0 +1,154 @@
+use hashbrown::HashMap;
+use image::{ImageFormat, RgbaImage};
+use image_dds::{Mipmaps, Quality};
+
+use super::{create_atlas_rgba, AtlasExporter, CompositeOptions};
+use crate::{
+    error::{AtlasPackerError, Result},
+    place::PlacedTextureGeometry,
+    texture::{cache::TextureCache, ClusterBoundingTexture},
+    ClusterID,
+};
+
+/// Block compression format of the DDS texture.
+/// Pair it with `TexturePlacerConfig::block_alignment` of 4,
+/// so that no 4x4 block is shared between clusters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BcFormat {
+    /// 4 bits per pixel, with 1-bit alpha (DXT1)
+    Bc1,
+    /// 8 bits per pixel, with interpolated alpha (DXT5)
+    #[default]
+    Bc3,
+    /// 8 bits per pixel, with the highest quality and the slowest encoding
+    Bc7,
+}
+
+impl BcFormat {
+    fn image_format(&self, srgb: bool) -> image_dds::ImageFormat {
+        match (self, srgb) {
+            (BcFormat::Bc1, false) => image_dds::ImageFormat::BC1RgbaUnorm,
+            (BcFormat::Bc1, true) => image_dds::ImageFormat::BC1RgbaUnormSrgb,
+            (BcFormat::Bc3, false) => image_dds::ImageFormat::BC3RgbaUnorm,
+            (BcFormat::Bc3, true) => image_dds::ImageFormat::BC3RgbaUnormSrgb,
+            (BcFormat::Bc7, false) => image_dds::ImageFormat::BC7RgbaUnorm,
+            (BcFormat::Bc7, true) => image_dds::ImageFormat::BC7RgbaUnormSrgb,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DdsAtlasExporter {
+    pub ext: String,
+    pub composite: CompositeOptions,
+    pub format: BcFormat,
+    /// Store the mip chain down to 1x1, each level block compressed on its own.
+    /// Levels smaller than 4x4 still take up a whole block.
+    pub generate_mipmaps: bool,
+    /// Use the `_SRGB` DXGI variant of the format, which the GPU decodes to linear when sampling.
+    /// BC1, BC3 and BC7 all have one; set it to false for normal maps and other linear data.
+    pub srgb: bool,
+}
+
+impl Default for DdsAtlasExporter {
+    fn default() -> Self {
+        DdsAtlasExporter {
+            ext: "dds".to_string(),
+            composite: CompositeOptions::default(),
+            format: BcFormat::default(),
+            generate_mipmaps: true,
+            srgb: true,
+        }
+    }
+}
+
+impl AtlasExporter for DdsAtlasExporter {
+    fn get_extension(&self) -> &str {
+        &self.ext
+    }
+
+    fn get_image_format(&self) -> Option<ImageFormat> {
+        Some(ImageFormat::Dds)
+    }
+
+    fn encode_atlas(
+        &self,
+        atlas_data: &[PlacedTextureGeometry],
+        textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+        texture_cache: &TextureCache,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>> {
+        let atlas_image = create_atlas_rgba(
+            atlas_data,
+            textures,
+            texture_cache,
+            width,
+            height,
+            &self.composite,
+        )?;
+        self.encode(&atlas_image)
+    }
+}
+
+impl DdsAtlasExporter {
+    /// Encode the image into a DDS file
+    pub fn encode(&self, image: &RgbaImage) -> Result<Vec<u8>> {
+        let mipmaps = if self.generate_mipmaps {
+            Mipmaps::GeneratedAutomatic
+        } else {
+            Mipmaps::Disabled
+        };
+        let dds = image_dds::dds_from_image(
+            image,
+            self.format.image_format(self.srgb),
+            Quality::Normal,
+            mipmaps,
+        )
+        .map_err(|e| AtlasPackerError::Encode(e.to_string()))?;
+
+        let mut file = Vec::new();
+        dds.write(&mut file)
+            .map_err(|e| AtlasPackerError::Encode(e.to_string()))?;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+    use image_dds::ddsfile::Dds;
+
+    use super::*;
+
+    #[test]
+    fn test_dds_roundtrip() {
+        // Two flat 4x4 blocks, which every BCn format encodes almost exactly
+        let image = RgbaImage::from_fn(8, 4, |x, _| {
+            if x < 4 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+
+        for format in [BcFormat::Bc1, BcFormat::Bc3, BcFormat::Bc7] {
+            let exporter = DdsAtlasExporter {
+                format,
+                ..Default::default()
+            };
+            let dds = Dds::read(exporter.encode(&image).unwrap().as_slice()).unwrap();
+            assert_eq!((dds.get_width(), dds.get_height()), (8, 4));
+            // 8x4, 4x2, 2x1 and 1x1
+            assert_eq!(dds.get_num_mipmap_levels(), 4);
+
+            let decoded = image_dds::image_from_dds(&dds, 0).unwrap();
+            for (x, expected) in [(0, image.get_pixel(0, 0)), (7, image.get_pixel(7, 0))] {
+                let pixel = decoded.get_pixel(x, 3);
+                for (actual, expected) in pixel.0.iter().zip(expected.0) {
+                    assert!(actual.abs_diff(expected) <= 8, "{:?}: {:?}", format, pixel);
+                }
+            }
+        }
+    }
+}
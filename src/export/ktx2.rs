@@ -0,0 +1,545 @@
+use std::num::NonZeroU8;
+use std::ops::RangeInclusive;
+
+use ::ktx2::{
+    dfd::{Basic, Block, ChannelTypeQualifiers, DataFormatFlags, SampleInformation},
+    ColorModel, ColorPrimaries, Header, Index, LevelIndex, SupercompressionScheme,
+    TransferFunction,
+};
+use basis_universal::{BasisTextureFormat, ColorSpace, Compressor, CompressorParams};
+use hashbrown::HashMap;
+use image::{ImageFormat, RgbaImage};
+
+use super::{create_atlas_rgba, AtlasExporter, CompositeOptions};
+use crate::{
+    error::{AtlasPackerError, Result},
+    place::PlacedTextureGeometry,
+    texture::{cache::TextureCache, ClusterBoundingTexture},
+    ClusterID,
+};
+
+/// Basis Universal codec of the KTX2 texture, as used by `KHR_texture_basisu`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BasisCodec {
+    /// Small files at a lower quality, supercompressed with BasisLZ
+    Etc1s {
+        /// 1 (lowest) to 255 (highest)
+        quality: u32,
+    },
+    /// High quality, optionally supercompressed with Zstandard
+    Uastc {
+        /// 0 (fastest) to 4 (slowest)
+        quality: u32,
+        zstd_level: Option<i32>,
+    },
+}
+
+impl Default for BasisCodec {
+    fn default() -> Self {
+        BasisCodec::Etc1s {
+            quality: basis_universal::ETC1S_QUALITY_DEFAULT,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Ktx2AtlasExporter {
+    pub ext: String,
+    pub composite: CompositeOptions,
+    pub codec: BasisCodec,
+    /// Let the Basis compressor generate the mip chain down to 1x1.
+    /// With ETC1S, every level is encoded with the same codebooks.
+    pub generate_mipmaps: bool,
+    /// Tag the file with the sRGB transfer function, and have the ETC1S encoder weight its
+    /// error perceptually. Set it to false for linear data such as normal maps,
+    /// which is then also downsampled without gamma when generating the mipmaps.
+    pub srgb: bool,
+}
+
+impl Default for Ktx2AtlasExporter {
+    fn default() -> Self {
+        Ktx2AtlasExporter {
+            ext: "ktx2".to_string(),
+            composite: CompositeOptions::default(),
+            codec: BasisCodec::default(),
+            generate_mipmaps: true,
+            srgb: true,
+        }
+    }
+}
+
+impl AtlasExporter for Ktx2AtlasExporter {
+    fn get_extension(&self) -> &str {
+        &self.ext
+    }
+
+    fn get_image_format(&self) -> Option<ImageFormat> {
+        None
+    }
+
+    fn encode_atlas(
+        &self,
+        atlas_data: &[PlacedTextureGeometry],
+        textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+        texture_cache: &TextureCache,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>> {
+        let atlas_image = create_atlas_rgba(
+            atlas_data,
+            textures,
+            texture_cache,
+            width,
+            height,
+            &self.composite,
+        )?;
+        self.encode(&atlas_image)
+    }
+}
+
+impl Ktx2AtlasExporter {
+    /// Encode the image into a KTX2 file
+    pub fn encode(&self, image: &RgbaImage) -> Result<Vec<u8>> {
+        let basis = self.compress(image)?;
+        write_ktx2(&BasisFile::parse(&basis)?, self.codec, self.srgb)
+    }
+
+    // Encode the image into a .basis file, whose slices are then repackaged into KTX2
+    fn compress(&self, image: &RgbaImage) -> Result<Vec<u8>> {
+        let mut params = CompressorParams::new();
+        match self.codec {
+            BasisCodec::Etc1s { quality } => {
+                check_quality(
+                    "ETC1S",
+                    quality,
+                    basis_universal::ETC1S_QUALITY_MIN..=basis_universal::ETC1S_QUALITY_MAX,
+                )?;
+                params.set_basis_format(BasisTextureFormat::ETC1S);
+                params.set_etc1s_quality_level(quality);
+            }
+            BasisCodec::Uastc { quality, .. } => {
+                check_quality(
+                    "UASTC",
+                    quality,
+                    basis_universal::UASTC_QUALITY_MIN..=basis_universal::UASTC_QUALITY_MAX,
+                )?;
+                params.set_basis_format(BasisTextureFormat::UASTC4x4);
+                params.set_uastc_quality_level(quality);
+            }
+        }
+
+        let color_space = if self.srgb {
+            ColorSpace::Srgb
+        } else {
+            ColorSpace::Linear
+        };
+        params.set_color_space(color_space);
+        params.set_mip_color_space(color_space);
+        params.set_generate_mipmaps(self.generate_mipmaps);
+        params
+            .source_image_mut(0)
+            .init(image.as_raw(), image.width(), image.height(), 4);
+
+        // The atlases are already encoded in parallel, so a single thread is used per atlas
+        let mut compressor = Compressor::new(1);
+        // SAFETY: the parameters are valid, as the quality levels have been checked above
+        unsafe {
+            if !compressor.init(&params) {
+                return Err(AtlasPackerError::Encode(
+                    "failed to initialize the Basis Universal compressor".to_string(),
+                ));
+            }
+            compressor
+                .process()
+                .map_err(|e| AtlasPackerError::Encode(format!("{:?}", e)))?;
+        }
+        Ok(compressor.basis_file().to_vec())
+    }
+}
+
+fn check_quality(codec: &str, quality: u32, range: RangeInclusive<u32>) -> Result<()> {
+    if range.contains(&quality) {
+        Ok(())
+    } else {
+        Err(AtlasPackerError::Encode(format!(
+            "{} quality must be within {:?}, got {}",
+            codec, range, quality
+        )))
+    }
+}
+
+// The parts of a .basis file that are stored in a KTX2 file (see basisu_file_headers.h)
+struct BasisFile<'a> {
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+    // Codebooks and Huffman tables shared by all ETC1S slices
+    endpoint_count: u16,
+    selector_count: u16,
+    endpoints: &'a [u8],
+    selectors: &'a [u8],
+    tables: &'a [u8],
+    // Compressed data of each mip level: the RGB(A) slice and, for ETC1S, the alpha slice
+    levels: Vec<(&'a [u8], Option<&'a [u8]>)>,
+}
+
+impl<'a> BasisFile<'a> {
+    const SLICE_DESC_SIZE: usize = 23;
+    const FLAG_HAS_ALPHA_SLICES: u32 = 4;
+    const FORMAT_ETC1S: u32 = 0;
+
+    fn parse(data: &'a [u8]) -> Result<Self> {
+        let total_slices = read_uint(data, 14, 3)? as usize;
+        let is_etc1s = read_uint(data, 20, 1)? == Self::FORMAT_ETC1S;
+        let has_alpha = read_uint(data, 21, 2)? & Self::FLAG_HAS_ALPHA_SLICES != 0;
+        let slice_desc_offset = read_uint(data, 65, 4)? as usize;
+
+        let slices = (0..total_slices)
+            .map(|i| {
+                let desc = slice_desc_offset + i * Self::SLICE_DESC_SIZE;
+                let offset = read_uint(data, desc + 13, 4)?;
+                let size = read_uint(data, desc + 17, 4)?;
+                read_bytes(data, offset, size)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // ETC1S files with alpha alternate between RGB and alpha slices
+        let levels = if is_etc1s && has_alpha {
+            slices
+                .chunks_exact(2)
+                .map(|slices| (slices[0], Some(slices[1])))
+                .collect()
+        } else {
+            slices.into_iter().map(|slice| (slice, None)).collect()
+        };
+
+        Ok(BasisFile {
+            width: read_uint(data, slice_desc_offset + 5, 2)?,
+            height: read_uint(data, slice_desc_offset + 7, 2)?,
+            has_alpha,
+            endpoint_count: read_uint(data, 39, 2)? as u16,
+            selector_count: read_uint(data, 48, 2)? as u16,
+            endpoints: read_bytes(data, read_uint(data, 41, 4)?, read_uint(data, 45, 3)?)?,
+            selectors: read_bytes(data, read_uint(data, 50, 4)?, read_uint(data, 54, 3)?)?,
+            tables: read_bytes(data, read_uint(data, 57, 4)?, read_uint(data, 61, 4)?)?,
+            levels,
+        })
+    }
+}
+
+// Read a little-endian unsigned integer of `size` bytes
+fn read_uint(data: &[u8], offset: usize, size: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + size).ok_or_else(invalid_basis)?;
+    Ok(bytes
+        .iter()
+        .rev()
+        .fold(0, |value, &byte| (value << 8) | byte as u32))
+}
+
+fn read_bytes(data: &[u8], offset: u32, size: u32) -> Result<&[u8]> {
+    data.get(offset as usize..(offset + size) as usize)
+        .ok_or_else(invalid_basis)
+}
+
+fn invalid_basis() -> AtlasPackerError {
+    AtlasPackerError::Encode("invalid Basis Universal output".to_string())
+}
+
+fn write_ktx2(basis: &BasisFile, codec: BasisCodec, srgb: bool) -> Result<Vec<u8>> {
+    // Data of each level, and its byte length before supercompression
+    let (supercompression_scheme, levels) = match codec {
+        BasisCodec::Etc1s { .. } => {
+            let levels = basis
+                .levels
+                .iter()
+                .map(|(rgb, alpha)| ([rgb, alpha.unwrap_or_default()].concat(), 0))
+                .collect::<Vec<_>>();
+            (Some(SupercompressionScheme::BasisLZ), levels)
+        }
+        BasisCodec::Uastc {
+            zstd_level: Some(zstd_level),
+            ..
+        } => {
+            let levels = basis
+                .levels
+                .iter()
+                .map(|(data, _)| Ok((zstd::bulk::compress(data, zstd_level)?, data.len() as u64)))
+                .collect::<Result<Vec<_>>>()?;
+            (Some(SupercompressionScheme::Zstandard), levels)
+        }
+        BasisCodec::Uastc {
+            zstd_level: None, ..
+        } => {
+            let levels = basis
+                .levels
+                .iter()
+                .map(|(data, _)| (data.to_vec(), data.len() as u64))
+                .collect::<Vec<_>>();
+            (None, levels)
+        }
+    };
+
+    let dfd = data_format_descriptor(basis, codec, srgb, supercompression_scheme.is_some());
+    let dfd_offset = Header::LENGTH + levels.len() * LevelIndex::LENGTH;
+    let mut offset = dfd_offset + dfd.len();
+
+    let sgd = match codec {
+        BasisCodec::Etc1s { .. } => supercompression_global_data(basis),
+        BasisCodec::Uastc { .. } => Vec::new(),
+    };
+    let sgd_offset = if sgd.is_empty() {
+        0
+    } else {
+        offset = offset.next_multiple_of(8);
+        offset
+    };
+    offset += sgd.len();
+
+    // Levels are stored from the smallest to the largest. Without supercompression,
+    // they are aligned to the UASTC block size.
+    let alignment = if supercompression_scheme.is_some() {
+        1
+    } else {
+        16
+    };
+    let mut level_indices = vec![None; levels.len()];
+    for (level, (data, uncompressed_byte_length)) in levels.iter().enumerate().rev() {
+        offset = offset.next_multiple_of(alignment);
+        level_indices[level] = Some(LevelIndex {
+            byte_offset: offset as u64,
+            byte_length: data.len() as u64,
+            uncompressed_byte_length: *uncompressed_byte_length,
+        });
+        offset += data.len();
+    }
+
+    let header = Header {
+        format: None,
+        type_size: 1,
+        pixel_width: basis.width,
+        pixel_height: basis.height,
+        pixel_depth: 0,
+        layer_count: 0,
+        face_count: 1,
+        level_count: levels.len() as u32,
+        supercompression_scheme,
+        index: Index {
+            dfd_byte_offset: dfd_offset as u32,
+            dfd_byte_length: dfd.len() as u32,
+            kvd_byte_offset: 0,
+            kvd_byte_length: 0,
+            sgd_byte_offset: sgd_offset as u64,
+            sgd_byte_length: sgd.len() as u64,
+        },
+    };
+
+    let mut file = Vec::with_capacity(offset);
+    file.extend_from_slice(&header.as_bytes());
+    for level_index in level_indices.iter().flatten() {
+        file.extend_from_slice(&level_index.as_bytes());
+    }
+    file.extend_from_slice(&dfd);
+    if !sgd.is_empty() {
+        file.resize(sgd_offset, 0);
+        file.extend_from_slice(&sgd);
+    }
+    for ((data, _), level_index) in levels.iter().zip(&level_indices).rev() {
+        file.resize(level_index.unwrap().byte_offset as usize, 0);
+        file.extend_from_slice(data);
+    }
+    Ok(file)
+}
+
+fn data_format_descriptor(
+    basis: &BasisFile,
+    codec: BasisCodec,
+    srgb: bool,
+    supercompressed: bool,
+) -> Vec<u8> {
+    let sample = |bit_offset: u16, bit_length: u8, channel_type: u8| SampleInformation {
+        bit_offset,
+        bit_length: NonZeroU8::new(bit_length).unwrap(),
+        channel_type,
+        channel_type_qualifiers: ChannelTypeQualifiers::empty(),
+        sample_positions: [0; 4],
+        lower: 0,
+        upper: u32::MAX,
+    };
+
+    // Channel types defined by the Khronos Data Format Specification
+    const ETC1S_RGB: u8 = 0;
+    const ETC1S_AAA: u8 = 15;
+    const UASTC_RGB: u8 = 0;
+    const UASTC_RGBA: u8 = 3;
+
+    let (color_model, sample_information, block_bytes) = match codec {
+        BasisCodec::Etc1s { .. } => {
+            let mut samples = vec![sample(0, 64, ETC1S_RGB)];
+            if basis.has_alpha {
+                samples.push(sample(64, 64, ETC1S_AAA));
+            }
+            (ColorModel::ETC1S, samples, 0)
+        }
+        BasisCodec::Uastc { .. } => {
+            let channel_type = if basis.has_alpha {
+                UASTC_RGBA
+            } else {
+                UASTC_RGB
+            };
+            (ColorModel::UASTC, vec![sample(0, 128, channel_type)], 16)
+        }
+    };
+
+    let four = NonZeroU8::new(4).unwrap();
+    let one = NonZeroU8::new(1).unwrap();
+    let block = Block::Basic(Basic {
+        color_model: Some(color_model),
+        color_primaries: Some(ColorPrimaries::BT709),
+        transfer_function: Some(if srgb {
+            TransferFunction::SRGB
+        } else {
+            TransferFunction::Linear
+        }),
+        flags: DataFormatFlags::STRAIGHT_ALPHA,
+        texel_block_dimensions: [four, four, one, one],
+        // The byte size of the blocks is left unspecified when the levels are supercompressed
+        bytes_planes: [
+            if supercompressed { 0 } else { block_bytes },
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ],
+        sample_information,
+    })
+    .to_vec();
+
+    let total_size = (4 + block.len()) as u32;
+    [&total_size.to_le_bytes()[..], &block].concat()
+}
+
+// BasisLZ global data: the codebooks, the Huffman tables and the slice locations of each level
+fn supercompression_global_data(basis: &BasisFile) -> Vec<u8> {
+    let mut sgd = Vec::new();
+    sgd.extend_from_slice(&basis.endpoint_count.to_le_bytes());
+    sgd.extend_from_slice(&basis.selector_count.to_le_bytes());
+    for data in [basis.endpoints, basis.selectors, basis.tables, &[]] {
+        sgd.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    }
+
+    for (rgb, alpha) in &basis.levels {
+        let alpha_length = alpha.map_or(0, |alpha| alpha.len()) as u32;
+        let alpha_offset = if alpha.is_some() { rgb.len() as u32 } else { 0 };
+        for value in [0, 0, rgb.len() as u32, alpha_offset, alpha_length] {
+            sgd.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    sgd.extend_from_slice(basis.endpoints);
+    sgd.extend_from_slice(basis.selectors);
+    sgd.extend_from_slice(basis.tables);
+    sgd
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    fn encode(codec: BasisCodec, opaque: bool) -> Vec<u8> {
+        let image = RgbaImage::from_fn(8, 8, |x, y| {
+            let alpha = if opaque { 255 } else { (x * 32) as u8 };
+            Rgba([(x * 32) as u8, (y * 32) as u8, 128, alpha])
+        });
+        let exporter = Ktx2AtlasExporter {
+            codec,
+            ..Default::default()
+        };
+        exporter.encode(&image).unwrap()
+    }
+
+    #[test]
+    fn test_ktx2_etc1s() {
+        for opaque in [true, false] {
+            let file = encode(BasisCodec::default(), opaque);
+            let reader = ::ktx2::Reader::new(&file).unwrap();
+            let header = reader.header();
+            assert_eq!((header.pixel_width, header.pixel_height), (8, 8));
+            // 8x8, 4x4, 2x2 and 1x1
+            assert_eq!(header.level_count, 4);
+            assert_eq!(
+                header.supercompression_scheme,
+                Some(SupercompressionScheme::BasisLZ)
+            );
+            assert_eq!(reader.color_model(), Some(ColorModel::ETC1S));
+            assert_eq!(reader.transfer_function(), Some(TransferFunction::SRGB));
+
+            let sample_count = reader.basic_dfd().unwrap().sample_information.len();
+            assert_eq!(sample_count, if opaque { 1 } else { 2 });
+
+            // The image descriptions of the global data cover the level data
+            let sgd = reader.supercompression_global_data();
+            for (level, data) in reader.levels().enumerate() {
+                let desc = &sgd[20 + level * 20..];
+                let rgb_length = read_uint(desc, 8, 4).unwrap();
+                let alpha_length = read_uint(desc, 16, 4).unwrap();
+                assert_eq!(data.data.len() as u32, rgb_length + alpha_length);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ktx2_uastc() {
+        let file = encode(
+            BasisCodec::Uastc {
+                quality: 1,
+                zstd_level: None,
+            },
+            true,
+        );
+        let reader = ::ktx2::Reader::new(&file).unwrap();
+        assert_eq!(reader.header().supercompression_scheme, None);
+        assert_eq!(reader.color_model(), Some(ColorModel::UASTC));
+        let levels = reader.levels().collect::<Vec<_>>();
+        // One 16-byte block per 4x4 texels
+        assert_eq!(levels[0].data.len(), 4 * 16);
+        assert_eq!(levels[3].data.len(), 16);
+        let uncompressed = levels[0].data.to_vec();
+
+        let file = encode(
+            BasisCodec::Uastc {
+                quality: 1,
+                zstd_level: Some(3),
+            },
+            true,
+        );
+        let reader = ::ktx2::Reader::new(&file).unwrap();
+        assert_eq!(
+            reader.header().supercompression_scheme,
+            Some(SupercompressionScheme::Zstandard)
+        );
+        let level = reader.levels().next().unwrap();
+        let decompressed =
+            zstd::bulk::decompress(level.data, level.uncompressed_byte_length as usize).unwrap();
+        assert_eq!(decompressed, uncompressed);
+    }
+
+    #[test]
+    fn test_ktx2_invalid_quality() {
+        let exporter = Ktx2AtlasExporter {
+            codec: BasisCodec::Uastc {
+                quality: 10,
+                zstd_level: None,
+            },
+            ..Default::default()
+        };
+        assert!(matches!(
+            exporter.encode(&RgbaImage::new(4, 4)),
+            Err(AtlasPackerError::Encode(_))
+        ));
+    }
+}
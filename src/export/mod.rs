@@ -0,0 +1,755 @@
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use hashbrown::HashMap;
+use image::{ImageBuffer, ImageFormat, Rgb, RgbImage, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use crate::{
+    error::{AtlasPackerError, Result},
+    place::PlacedTextureGeometry,
+    texture::{cache::TextureCache, ClusterBoundingTexture, PolygonMask},
+    ClusterID,
+};
+
+#[cfg(feature = "avif")]
+mod avif;
+#[cfg(feature = "dds")]
+mod dds;
+#[cfg(feature = "ktx2")]
+mod ktx2;
+
+#[cfg(feature = "avif")]
+pub use avif::AvifAtlasExporter;
+#[cfg(feature = "dds")]
+pub use dds::{BcFormat, DdsAtlasExporter};
+#[cfg(feature = "ktx2")]
+pub use ktx2::{BasisCodec, Ktx2AtlasExporter};
+
+pub trait AtlasExporter: Sync + Send {
+    /// Composite the atlas and encode it into the bytes of an image file
+    fn encode_atlas(
+        &self,
+        atlas_data: &[PlacedTextureGeometry],
+        textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+        texture_cache: &TextureCache,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>>;
+
+    /// Composite the atlas and write the encoded image into `writer`
+    fn export_to_writer(
+        &self,
+        atlas_data: &[PlacedTextureGeometry],
+        textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+        writer: &mut dyn Write,
+        texture_cache: &TextureCache,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let encoded = self.encode_atlas(atlas_data, textures, texture_cache, width, height)?;
+        writer.write_all(&encoded)?;
+        Ok(())
+    }
+
+    /// Composite the atlas and write it into `output_path`, with the extension of the exporter
+    fn export(
+        &self,
+        atlas_data: &[PlacedTextureGeometry],
+        textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+        output_path: &Path,
+        texture_cache: &TextureCache,
+        width: u32,
+        height: u32,
+    ) -> Result<()> {
+        let output_path = output_path.with_extension(self.get_extension());
+        let mut file = BufWriter::new(File::create(output_path)?);
+        self.export_to_writer(
+            atlas_data,
+            textures,
+            &mut file,
+            texture_cache,
+            width,
+            height,
+        )?;
+        file.flush()?;
+        Ok(())
+    }
+
+    fn get_extension(&self) -> &str;
+    /// `None` for formats that the `image` crate does not handle, such as KTX2
+    fn get_image_format(&self) -> Option<ImageFormat>;
+}
+
+/// Options for compositing the cropped textures into an atlas image
+#[derive(Debug, Clone, Default)]
+pub struct CompositeOptions {
+    /// Mask out the pixels that are not covered by any polygon
+    pub mask: Option<PolygonMask>,
+    /// Extend the pixels of each cluster into its padding
+    pub bleed: Option<EdgeBleed>,
+}
+
+#[derive(Clone)]
+pub struct WebpAtlasExporter {
+    pub ext: String,
+    pub composite: CompositeOptions,
+    /// 0 to 100. In lossless mode, the effort spent on compressing
+    pub quality: f32,
+    pub lossless: bool,
+    /// 0 (fastest) to 6 (slowest, smallest)
+    pub method: i32,
+    /// 0 to 100, for lossy compression of the alpha channel
+    pub alpha_quality: i32,
+    /// Use the slower but sharper RGB to YUV conversion; lossy WebP is always 4:2:0
+    pub sharp_yuv: bool,
+}
+
+impl Default for WebpAtlasExporter {
+    fn default() -> Self {
+        WebpAtlasExporter {
+            ext: "webp".to_string(),
+            composite: CompositeOptions::default(),
+            quality: 75.0,
+            lossless: false,
+            method: 4,
+            alpha_quality: 100,
+            sharp_yuv: false,
+        }
+    }
+}
+
+impl AtlasExporter for WebpAtlasExporter {
+    fn get_extension(&self) -> &str {
+        &self.ext
+    }
+
+    fn get_image_format(&self) -> Option<ImageFormat> {
+        Some(ImageFormat::WebP)
+    }
+
+    fn encode_atlas(
+        &self,
+        atlas_data: &[PlacedTextureGeometry],
+        textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+        texture_cache: &TextureCache,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>> {
+        let atlas_image = create_atlas_rgba(
+            atlas_data,
+            textures,
+            texture_cache,
+            width,
+            height,
+            &self.composite,
+        )?;
+        self.encode(&atlas_image)
+    }
+}
+
+impl WebpAtlasExporter {
+    /// Encode the image into a WebP file
+    pub fn encode(&self, image: &RgbaImage) -> Result<Vec<u8>> {
+        let mut config = webp::WebPConfig::new()
+            .map_err(|_| AtlasPackerError::Encode("invalid WebP config".to_string()))?;
+        config.lossless = self.lossless as i32;
+        config.quality = self.quality;
+        config.method = self.method;
+        config.alpha_quality = self.alpha_quality;
+        config.use_sharp_yuv = self.sharp_yuv as i32;
+
+        let webp = webp::Encoder::from_rgba(image.as_raw(), image.width(), image.height())
+            .encode_advanced(&config)
+            .map_err(|e| AtlasPackerError::Encode(format!("{:?}", e)))?;
+        Ok(webp.to_vec())
+    }
+}
+
+#[derive(Clone)]
+pub struct PngAtlasExporter {
+    pub ext: String,
+    pub composite: CompositeOptions,
+}
+
+impl Default for PngAtlasExporter {
+    fn default() -> Self {
+        PngAtlasExporter {
+            ext: "png".to_string(),
+            composite: CompositeOptions::default(),
+        }
+    }
+}
+
+impl AtlasExporter for PngAtlasExporter {
+    fn get_extension(&self) -> &str {
+        &self.ext
+    }
+
+    fn get_image_format(&self) -> Option<ImageFormat> {
+        Some(ImageFormat::Png)
+    }
+
+    fn encode_atlas(
+        &self,
+        atlas_data: &[PlacedTextureGeometry],
+        textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+        texture_cache: &TextureCache,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>> {
+        let atlas_image = create_atlas_rgba(
+            atlas_data,
+            textures,
+            texture_cache,
+            width,
+            height,
+            &self.composite,
+        )?;
+        self.encode(&atlas_image)
+    }
+}
+
+impl PngAtlasExporter {
+    /// Encode the image into a PNG file
+    pub fn encode(&self, image: &RgbaImage) -> Result<Vec<u8>> {
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageFormat::Png)?;
+        Ok(png.into_inner())
+    }
+}
+
+/// Resolution of the chroma channels of a JPEG relative to the luma channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChromaSubsampling {
+    /// Full resolution chroma
+    Yuv444,
+    /// Half horizontal resolution
+    Yuv422,
+    /// Half horizontal and vertical resolution
+    #[default]
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    fn sampling_factor(&self) -> jpeg_encoder::SamplingFactor {
+        match self {
+            ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct JpegAtlasExporter {
+    pub ext: String,
+    pub composite: CompositeOptions,
+    /// 1 to 100
+    pub quality: u8,
+    pub chroma_subsampling: ChromaSubsampling,
+}
+
+impl Default for JpegAtlasExporter {
+    fn default() -> Self {
+        JpegAtlasExporter {
+            ext: "jpg".to_string(),
+            composite: CompositeOptions::default(),
+            quality: 75,
+            chroma_subsampling: ChromaSubsampling::default(),
+        }
+    }
+}
+
+impl AtlasExporter for JpegAtlasExporter {
+    fn get_extension(&self) -> &str {
+        &self.ext
+    }
+
+    fn get_image_format(&self) -> Option<ImageFormat> {
+        Some(ImageFormat::Jpeg)
+    }
+
+    fn encode_atlas(
+        &self,
+        atlas_data: &[PlacedTextureGeometry],
+        textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+        texture_cache: &TextureCache,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<u8>> {
+        let atlas_image = create_atlas_image_rgb(
+            atlas_data,
+            textures,
+            texture_cache,
+            width,
+            height,
+            &self.composite,
+        )?;
+        self.encode(&atlas_image)
+    }
+}
+
+impl JpegAtlasExporter {
+    /// Encode the image into a JPEG file
+    pub fn encode(&self, image: &RgbImage) -> Result<Vec<u8>> {
+        let (Ok(width), Ok(height)) = (u16::try_from(image.width()), u16::try_from(image.height()))
+        else {
+            return Err(AtlasPackerError::Encode(format!(
+                "JPEG images cannot exceed 65535x65535, got {}x{}",
+                image.width(),
+                image.height()
+            )));
+        };
+        if !(1..=100).contains(&self.quality) {
+            return Err(AtlasPackerError::Encode(format!(
+                "JPEG quality must be within 1..=100, got {}",
+                self.quality
+            )));
+        }
+
+        let mut jpeg = Vec::new();
+        let mut encoder = jpeg_encoder::Encoder::new(&mut jpeg, self.quality);
+        encoder.set_sampling_factor(self.chroma_subsampling.sampling_factor());
+        encoder
+            .encode(image.as_raw(), width, height, jpeg_encoder::ColorType::Rgb)
+            .map_err(|e| AtlasPackerError::Encode(e.to_string()))?;
+        Ok(jpeg)
+    }
+}
+
+fn create_atlas_rgba(
+    atlas_data: &[PlacedTextureGeometry],
+    textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+    texture_cache: &TextureCache,
+    width: u32,
+    height: u32,
+    options: &CompositeOptions,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>> {
+    let atlas_image = Mutex::new(ImageBuffer::new(width, height));
+
+    atlas_data.par_iter().try_for_each(|info| -> Result<()> {
+        let texture = textures
+            .get(&info.cluster_id)
+            .ok_or_else(|| AtlasPackerError::ClusterNotFound(info.cluster_id.clone()))?;
+        let mut cropped = texture.crop(
+            &texture_cache.get_image(&texture.source)?,
+            options.mask.as_ref(),
+        );
+        if info.rotated {
+            cropped = cropped.rotate90();
+        }
+        let image = cropped.to_rgba8();
+
+        let mut atlas_image = atlas_image.lock().unwrap();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let atlas_x = info.origin.0 + x;
+            let atlas_y = info.origin.1 + y;
+            atlas_image.put_pixel(atlas_x, atlas_y, *pixel);
+        }
+        Ok(())
+    })?;
+
+    let mut atlas_image = atlas_image.into_inner().unwrap();
+    if let Some(bleed) = &options.bleed {
+        bleed.apply(&mut atlas_image, atlas_data);
+    }
+    Ok(atlas_image)
+}
+
+fn create_atlas_image_rgb(
+    atlas_data: &[PlacedTextureGeometry],
+    textures: &HashMap<ClusterID, ClusterBoundingTexture>,
+    texture_cache: &TextureCache,
+    width: u32,
+    height: u32,
+    options: &CompositeOptions,
+) -> Result<ImageBuffer<Rgb<u8>, Vec<u8>>> {
+    // Composite with alpha so that the masking and bleeding work the same as for RGBA,
+    // then flatten it over the fill colour of the mask, or black without one
+    let atlas_image =
+        create_atlas_rgba(atlas_data, textures, texture_cache, width, height, options)?;
+    let background = options
+        .mask
+        .as_ref()
+        .map_or([0, 0, 0], |mask| [mask.fill[0], mask.fill[1], mask.fill[2]]);
+    Ok(RgbImage::from_fn(width, height, |x, y| {
+        let pixel = atlas_image.get_pixel(x, y);
+        let alpha = pixel[3] as u32;
+        Rgb(std::array::from_fn(|i| {
+            ((pixel[i] as u32 * alpha + background[i] as u32 * (255 - alpha) + 127) / 255) as u8
+        }))
+    }))
+}
+
+/// How the pixels of each cluster are extended into the surrounding gutter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BleedMode {
+    /// Repeat the outermost pixels of the cluster
+    Clamp,
+    /// Mirror the pixels of the cluster at its edges
+    Mirror,
+    /// Grow the non-transparent pixels of each cluster outward one pixel at a time, averaging their neighbours.
+    /// Only the gutter is filled, so the pixels removed by the polygon mask stay transparent.
+    Dilate,
+}
+
+/// Extend the pixels of the placed clusters into the padding,
+/// so that bilinear filtering and mipmapping do not sample the empty gutter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeBleed {
+    pub mode: BleedMode,
+    /// Number of pixels to extend; usually the padding of the placer
+    pub width: u32,
+}
+
+impl EdgeBleed {
+    fn apply(&self, image: &mut RgbaImage, atlas_data: &[PlacedTextureGeometry]) {
+        let (cluster_rects, mut occupied) = cluster_rects(image, atlas_data);
+        match self.mode {
+            BleedMode::Clamp | BleedMode::Mirror => {
+                self.extend_clusters(image, &cluster_rects, &mut occupied)
+            }
+            BleedMode::Dilate => self.dilate(image, &cluster_rects, &mut occupied),
+        }
+    }
+
+    // Clusters are processed in order and never overwrite a cluster or an earlier bleed
+    fn extend_clusters(
+        &self,
+        image: &mut RgbaImage,
+        cluster_rects: &[ClusterRect],
+        occupied: &mut [bool],
+    ) {
+        let (width, height) = (image.width() as i64, image.height() as i64);
+        let bleed = self.width as i64;
+        for &(x, y, cluster_width, cluster_height) in cluster_rects {
+            for atlas_y in (y - bleed).max(0)..(y + cluster_height + bleed).min(height) {
+                for atlas_x in (x - bleed).max(0)..(x + cluster_width + bleed).min(width) {
+                    let index = (atlas_y * width + atlas_x) as usize;
+                    if occupied[index] {
+                        continue;
+                    }
+                    let source_x = x + self.source_offset(atlas_x - x, cluster_width);
+                    let source_y = y + self.source_offset(atlas_y - y, cluster_height);
+                    let pixel = *image.get_pixel(source_x as u32, source_y as u32);
+                    image.put_pixel(atlas_x as u32, atlas_y as u32, pixel);
+                    occupied[index] = true;
+                }
+            }
+        }
+    }
+
+    // Offset inside the cluster from which a pixel at `offset` (possibly outside) is copied
+    fn source_offset(&self, offset: i64, size: i64) -> i64 {
+        match self.mode {
+            BleedMode::Mirror if offset < 0 => (-offset - 1).min(size - 1),
+            BleedMode::Mirror if offset >= size => (2 * size - offset - 1).max(0),
+            _ => offset.clamp(0, size - 1),
+        }
+    }
+
+    // Each cluster grows only its own pixels, and only into the gutter around it.
+    // Like `extend_clusters`, clusters are processed in order and never overwrite a cluster or an earlier bleed.
+    fn dilate(&self, image: &mut RgbaImage, cluster_rects: &[ClusterRect], occupied: &mut [bool]) {
+        let (width, height) = (image.width() as i64, image.height() as i64);
+        let bleed = self.width as i64;
+        for &(x, y, cluster_width, cluster_height) in cluster_rects {
+            let (left, top) = ((x - bleed).max(0), (y - bleed).max(0));
+            let right = (x + cluster_width + bleed).min(width);
+            let bottom = (y + cluster_height + bleed).min(height);
+            let region_width = right - left;
+            let region_index = |atlas_x: i64, atlas_y: i64| {
+                ((atlas_y - top) * region_width + atlas_x - left) as usize
+            };
+
+            // Pixels of the region that belong to this cluster: its visible pixels and its bleed so far
+            let mut owned = vec![false; (region_width * (bottom - top)) as usize];
+            for atlas_y in y..y + cluster_height {
+                for atlas_x in x..x + cluster_width {
+                    owned[region_index(atlas_x, atlas_y)] =
+                        image.get_pixel(atlas_x as u32, atlas_y as u32)[3] > 0;
+                }
+            }
+
+            for _ in 0..bleed {
+                let mut grown = Vec::new();
+                for atlas_y in top..bottom {
+                    for atlas_x in left..right {
+                        if occupied[(atlas_y * width + atlas_x) as usize] {
+                            continue;
+                        }
+
+                        let mut sum = [0u32; 4];
+                        let mut count = 0;
+                        for neighbour_y in (atlas_y - 1).max(top)..(atlas_y + 2).min(bottom) {
+                            for neighbour_x in (atlas_x - 1).max(left)..(atlas_x + 2).min(right) {
+                                if !owned[region_index(neighbour_x, neighbour_y)] {
+                                    continue;
+                                }
+                                let neighbour =
+                                    image.get_pixel(neighbour_x as u32, neighbour_y as u32);
+                                for (sum, value) in sum.iter_mut().zip(neighbour.0) {
+                                    *sum += value as u32;
+                                }
+                                count += 1;
+                            }
+                        }
+                        if count > 0 {
+                            grown.push((
+                                atlas_x,
+                                atlas_y,
+                                Rgba(sum.map(|sum| (sum / count) as u8)),
+                            ));
+                        }
+                    }
+                }
+
+                for (atlas_x, atlas_y, pixel) in grown {
+                    image.put_pixel(atlas_x as u32, atlas_y as u32, pixel);
+                    owned[region_index(atlas_x, atlas_y)] = true;
+                    occupied[(atlas_y * width + atlas_x) as usize] = true;
+                }
+            }
+        }
+    }
+}
+
+// X, Y, width and height of a placed cluster
+type ClusterRect = (i64, i64, i64, i64);
+
+// Placed rectangles clipped to the image, and which pixels of the image they cover
+fn cluster_rects(
+    image: &RgbaImage,
+    atlas_data: &[PlacedTextureGeometry],
+) -> (Vec<ClusterRect>, Vec<bool>) {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let cluster_rects = atlas_data
+        .iter()
+        .map(|info| {
+            let (x, y) = (info.origin.0 as i64, info.origin.1 as i64);
+            let cluster_width = (info.width as i64).min(width - x);
+            let cluster_height = (info.height as i64).min(height - y);
+            (x, y, cluster_width, cluster_height)
+        })
+        .filter(|&(_, _, cluster_width, cluster_height)| cluster_width > 0 && cluster_height > 0)
+        .collect::<Vec<_>>();
+
+    let mut occupied = vec![false; (width * height) as usize];
+    for &(x, y, cluster_width, cluster_height) in &cluster_rects {
+        for atlas_y in y..y + cluster_height {
+            let row = (atlas_y * width) as usize;
+            occupied[row + x as usize..row + (x + cluster_width) as usize].fill(true);
+        }
+    }
+    (cluster_rects, occupied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::DynamicImage;
+
+    use crate::texture::{DownsampleFactor, PolygonMappedTexture, TextureSource};
+
+    // A 2x2 cluster with distinct pixels at (2, 2) on a 6x6 atlas
+    fn atlas_with_cluster() -> (RgbaImage, Vec<PlacedTextureGeometry>) {
+        let mut image = RgbaImage::new(6, 6);
+        image.put_pixel(2, 2, Rgba([10, 0, 0, 255]));
+        image.put_pixel(3, 2, Rgba([20, 0, 0, 255]));
+        image.put_pixel(2, 3, Rgba([30, 0, 0, 255]));
+        image.put_pixel(3, 3, Rgba([40, 0, 0, 255]));
+        let atlas_data = vec![PlacedTextureGeometry {
+            cluster_id: "0".to_string(),
+            atlas_id: 0,
+            origin: (2, 2),
+            width: 2,
+            height: 2,
+            rotated: false,
+        }];
+        (image, atlas_data)
+    }
+
+    fn red_row(image: &RgbaImage, y: u32) -> Vec<u8> {
+        (0..image.width())
+            .map(|x| image.get_pixel(x, y)[0])
+            .collect()
+    }
+
+    #[test]
+    fn test_edge_bleed() {
+        let (mut image, atlas_data) = atlas_with_cluster();
+        EdgeBleed {
+            mode: BleedMode::Clamp,
+            width: 2,
+        }
+        .apply(&mut image, &atlas_data);
+        assert_eq!(red_row(&image, 0), [10, 10, 10, 20, 20, 20]);
+        assert_eq!(red_row(&image, 5), [30, 30, 30, 40, 40, 40]);
+
+        let (mut image, atlas_data) = atlas_with_cluster();
+        EdgeBleed {
+            mode: BleedMode::Mirror,
+            width: 2,
+        }
+        .apply(&mut image, &atlas_data);
+        assert_eq!(red_row(&image, 2), [20, 10, 10, 20, 20, 10]);
+        assert_eq!(red_row(&image, 0), [40, 30, 30, 40, 40, 30]);
+
+        let (mut image, atlas_data) = atlas_with_cluster();
+        EdgeBleed {
+            mode: BleedMode::Dilate,
+            width: 1,
+        }
+        .apply(&mut image, &atlas_data);
+        assert_eq!(red_row(&image, 2), [0, 20, 10, 20, 30, 0]);
+        assert_eq!(image.get_pixel(1, 1)[0], 10);
+        assert_eq!(image.get_pixel(0, 0)[3], 0);
+
+        // Two clusters sharing a one-pixel gutter, the first with a pixel removed by the mask
+        let mut image = RgbaImage::new(7, 4);
+        for (x, y) in [(1, 1), (2, 1), (1, 2)] {
+            image.put_pixel(x, y, Rgba([10, 0, 0, 255]));
+        }
+        for (x, y) in [(4, 1), (5, 1), (4, 2), (5, 2)] {
+            image.put_pixel(x, y, Rgba([50, 0, 0, 255]));
+        }
+        let atlas_data = [(0, 1), (1, 4)]
+            .map(|(cluster_id, x)| PlacedTextureGeometry {
+                cluster_id: cluster_id.to_string(),
+                atlas_id: 0,
+                origin: (x, 1),
+                width: 2,
+                height: 2,
+                rotated: false,
+            })
+            .to_vec();
+        EdgeBleed {
+            mode: BleedMode::Dilate,
+            width: 1,
+        }
+        .apply(&mut image, &atlas_data);
+        assert_eq!(image.get_pixel(2, 2)[3], 0);
+        assert_eq!(red_row(&image, 1), [10, 10, 10, 10, 50, 50, 50]);
+    }
+
+    #[test]
+    fn test_missing_cluster_texture() {
+        let (_, atlas_data) = atlas_with_cluster();
+        let texture_cache = TextureCache::new(1_000_000);
+        let result = create_atlas_rgba(
+            &atlas_data,
+            &HashMap::new(),
+            &texture_cache,
+            6,
+            6,
+            &CompositeOptions::default(),
+        );
+        assert!(matches!(
+            result,
+            Err(AtlasPackerError::ClusterNotFound(cluster_id)) if cluster_id == "0"
+        ));
+    }
+
+    #[test]
+    fn test_rgb_masked_fill() {
+        // Red image masked by the lower-left half triangle, on an atlas twice as wide
+        let source = TextureSource::image(
+            "red",
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255]))),
+        );
+        let texture = PolygonMappedTexture::new(
+            source,
+            (8, 8),
+            &[(0.0, 1.0), (1.0, 0.0), (0.0, 0.0)],
+            DownsampleFactor::new(&1.0).unwrap(),
+        );
+        let textures = HashMap::from([("0".to_string(), ClusterBoundingTexture::new(&texture))]);
+        let atlas_data = [PlacedTextureGeometry {
+            cluster_id: "0".to_string(),
+            atlas_id: 0,
+            origin: (0, 0),
+            width: 8,
+            height: 8,
+            rotated: false,
+        }];
+        // Transparent fill, so that the RGBA atlas only differs from it in alpha
+        let options = CompositeOptions {
+            mask: Some(PolygonMask {
+                dilation: 0.0,
+                samples: 4,
+                fill: Rgba([0, 0, 255, 0]),
+            }),
+            bleed: None,
+        };
+
+        let texture_cache = TextureCache::new(1_000_000);
+        let image = create_atlas_image_rgb(&atlas_data, &textures, &texture_cache, 16, 8, &options)
+            .unwrap();
+        assert_eq!(image.get_pixel(0, 7), &Rgb([255, 0, 0]));
+        assert_eq!(image.get_pixel(7, 0), &Rgb([0, 0, 255]));
+        assert_eq!(image.get_pixel(12, 4), &Rgb([0, 0, 255]));
+        // Partly covered edge pixels are blended with the fill
+        let edge = image.get_pixel(3, 3);
+        assert!(edge[0] > 0 && edge[2] > 0, "{:?}", edge);
+
+        let jpeg = JpegAtlasExporter::default().encode(&image).unwrap();
+        let decoded = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        let background = decoded.get_pixel(12, 4);
+        assert!(
+            background[2] > 200 && background[0] < 50,
+            "{:?}",
+            background
+        );
+    }
+
+    #[test]
+    fn test_webp_options() {
+        let (mut image, _) = atlas_with_cluster();
+        image.put_pixel(0, 5, Rgba([1, 2, 3, 4]));
+
+        let exporter = WebpAtlasExporter {
+            lossless: true,
+            ..Default::default()
+        };
+        let decoded = image::load_from_memory_with_format(
+            &exporter.encode(&image).unwrap(),
+            ImageFormat::WebP,
+        )
+        .unwrap();
+        assert_eq!(decoded.to_rgba8(), image);
+
+        let exporter = WebpAtlasExporter {
+            method: 7,
+            ..Default::default()
+        };
+        assert!(exporter.encode(&image).is_err());
+    }
+
+    #[test]
+    fn test_jpeg_options() {
+        let image = RgbImage::from_pixel(16, 16, Rgb([200, 100, 50]));
+
+        // Sampling factors of the luma component in the start of frame segment
+        let luma_sampling = |chroma_subsampling| {
+            let exporter = JpegAtlasExporter {
+                chroma_subsampling,
+                ..Default::default()
+            };
+            let jpeg = exporter.encode(&image).unwrap();
+            let sof = jpeg.windows(2).position(|marker| marker == [0xFF, 0xC0]);
+            jpeg[sof.unwrap() + 11]
+        };
+        assert_eq!(luma_sampling(ChromaSubsampling::Yuv444), 0x11);
+        assert_eq!(luma_sampling(ChromaSubsampling::Yuv422), 0x21);
+        assert_eq!(luma_sampling(ChromaSubsampling::Yuv420), 0x22);
+
+        let exporter = JpegAtlasExporter {
+            quality: 0,
+            ..Default::default()
+        };
+        assert!(exporter.encode(&image).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 mod disjoint_set;
+pub mod error;
 pub mod export;
 pub mod pack;
 pub mod place;
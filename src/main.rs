@@ -0,0 +1,459 @@
+//! Packs the polygons listed in a manifest into atlases.
+//!
+//! The manifest is either a JSON array of polygons:
+//!
+//! ```json
+//! [{ "id": "wall_1", "texture": "a.png", "uv": [[0.1, 0.2], [0.9, 0.2], [0.5, 0.8]], "downsample_factor": 1.0 }]
+//! ```
+//!
+//! or a CSV file with the header `id,texture,downsample_factor,uv`,
+//! where `uv` lists the coordinates separated by spaces (`u0 v0 u1 v1 ...`), and fields are not quoted.
+//! `downsample_factor` may be omitted, and texture paths are relative to the manifest.
+//!
+//! The atlases are written into the output directory, along with `manifest.json` describing their layout
+//! and a UV remap table (`uv_remap.json` or `uv_remap.csv`, matching the input) with the placed UVs of every polygon.
+//!
+//! WebP, PNG and JPEG are always available. The AVIF, KTX2 and DDS formats are enabled by building with
+//! the features of the same names, e.g. `cargo install atlas-packer --features avif,ktx2,dds`.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, ValueEnum};
+use rayon::prelude::*;
+use serde_json::{json, Value};
+
+#[cfg(feature = "avif")]
+use atlas_packer::export::AvifAtlasExporter;
+#[cfg(feature = "dds")]
+use atlas_packer::export::DdsAtlasExporter;
+#[cfg(feature = "ktx2")]
+use atlas_packer::export::Ktx2AtlasExporter;
+use atlas_packer::{
+    export::{AtlasExporter, JpegAtlasExporter, PngAtlasExporter, WebpAtlasExporter},
+    pack::{AtlasPacker, AtlasPackerConfig, ClusterLimits, OverlapTest, PackedAtlasProvider},
+    place::{
+        GuillotineTexturePlacer, MaxRectsHeuristic, MaxRectsTexturePlacer, SkylineTexturePlacer,
+        TexturePlacerConfig,
+    },
+    texture::{
+        cache::{TextureCache, TextureSizeCache},
+        DownsampleFactor, PolygonMappedTexture, TextureSource,
+    },
+    PolygonID,
+};
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Pack the polygons listed in a JSON or CSV manifest into texture atlases"
+)]
+struct Args {
+    /// JSON or CSV manifest of the polygons
+    input: PathBuf,
+    /// Directory to write the atlases into
+    #[arg(short, long, default_value = "output")]
+    output_dir: PathBuf,
+    #[arg(long, value_enum, default_value_t = Placer::Guillotine)]
+    placer: Placer,
+    /// Rule for choosing the free rectangle, with the max-rects placer
+    #[arg(long, value_enum, default_value_t = Heuristic::BestShortSide)]
+    heuristic: Heuristic,
+    /// Do not reuse the gaps left under the skyline, with the skyline placer
+    #[arg(long)]
+    no_waste_map: bool,
+    /// Width of the atlases, rounded up to a power of two
+    #[arg(long, default_value_t = 4096)]
+    width: u32,
+    /// Height of the atlases, rounded up to a power of two
+    #[arg(long, default_value_t = 4096)]
+    height: u32,
+    #[arg(long, default_value_t = 0)]
+    padding: u32,
+    /// Allow textures to be rotated by 90 degrees
+    #[arg(long)]
+    allow_rotation: bool,
+    /// Alignment of the placed textures, 4 by default for block-compressed formats and 1 otherwise
+    #[arg(long)]
+    block_alignment: Option<u32>,
+    #[arg(short, long, value_enum, default_value_t = Format::Webp)]
+    format: Format,
+    /// Cluster polygons only when they overlap or come within this many pixels,
+    /// instead of whenever their bounding boxes overlap
+    #[arg(long)]
+    overlap_tolerance: Option<f64>,
+    /// Refuse merges that leave less than this fraction of a cluster covered by its members' crops
+    #[arg(long)]
+    min_fill_ratio: Option<f64>,
+    /// Refuse merges that make a cluster larger than this fraction of the atlas size
+    #[arg(long)]
+    max_cluster_size: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Placer {
+    Guillotine,
+    MaxRects,
+    Skyline,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Heuristic {
+    BestShortSide,
+    BestLongSide,
+    BestArea,
+    BottomLeft,
+    ContactPoint,
+}
+
+impl From<Heuristic> for MaxRectsHeuristic {
+    fn from(heuristic: Heuristic) -> Self {
+        match heuristic {
+            Heuristic::BestShortSide => MaxRectsHeuristic::BestShortSideFit,
+            Heuristic::BestLongSide => MaxRectsHeuristic::BestLongSideFit,
+            Heuristic::BestArea => MaxRectsHeuristic::BestAreaFit,
+            Heuristic::BottomLeft => MaxRectsHeuristic::BottomLeft,
+            Heuristic::ContactPoint => MaxRectsHeuristic::ContactPoint,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Webp,
+    Png,
+    Jpeg,
+    #[cfg(feature = "avif")]
+    Avif,
+    #[cfg(feature = "ktx2")]
+    Ktx2,
+    #[cfg(feature = "dds")]
+    Dds,
+}
+
+impl Format {
+    fn block_alignment(&self) -> u32 {
+        match self {
+            #[cfg(feature = "ktx2")]
+            Format::Ktx2 => 4,
+            #[cfg(feature = "dds")]
+            Format::Dds => 4,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Polygon {
+    id: PolygonID,
+    texture: PathBuf,
+    uv_coords: Vec<(f64, f64)>,
+    downsample_factor: f32,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let is_csv = args
+        .input
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+    let manifest = std::fs::read_to_string(&args.input)?;
+    let mut polygons = if is_csv {
+        parse_csv(&manifest)?
+    } else {
+        parse_json(&manifest)?
+    };
+    let base_dir = args.input.parent().unwrap_or(Path::new(""));
+    for polygon in polygons.iter_mut() {
+        polygon.texture = base_dir.join(&polygon.texture);
+    }
+
+    let texture_size_cache = TextureSizeCache::new();
+    let textures = polygons
+        .par_iter()
+        .map(|polygon| {
+            let source = TextureSource::path(&polygon.texture);
+            let size = texture_size_cache.get_or_insert(&source)?;
+            let texture = PolygonMappedTexture::new(
+                source,
+                size,
+                &polygon.uv_coords,
+                DownsampleFactor::new(&polygon.downsample_factor)?,
+            );
+            Ok((polygon.id.clone(), texture))
+        })
+        .collect::<atlas_packer::error::Result<Vec<_>>>()?;
+
+    let overlap_test = match args.overlap_tolerance {
+        Some(tolerance) => OverlapTest::Polygon { tolerance },
+        None => OverlapTest::BoundingBox,
+    };
+    let cluster_limits =
+        (args.min_fill_ratio.is_some() || args.max_cluster_size.is_some()).then(|| {
+            let no_limits = ClusterLimits::default();
+            ClusterLimits {
+                min_fill_ratio: args.min_fill_ratio.unwrap_or(no_limits.min_fill_ratio),
+                max_size_ratio: args.max_cluster_size.unwrap_or(no_limits.max_size_ratio),
+            }
+        });
+    let mut packer = AtlasPacker::new(AtlasPackerConfig {
+        overlap_test,
+        cluster_limits,
+        ..Default::default()
+    });
+    for (polygon_id, texture) in textures {
+        packer.add_texture(polygon_id, texture);
+    }
+
+    let config = TexturePlacerConfig {
+        allow_rotation: args.allow_rotation,
+        block_alignment: args
+            .block_alignment
+            .unwrap_or(args.format.block_alignment()),
+        ..TexturePlacerConfig::new(args.width, args.height, args.padding)
+    };
+    let packed = match args.placer {
+        Placer::Guillotine => packer.pack(GuillotineTexturePlacer::new(config))?,
+        Placer::MaxRects => {
+            packer.pack(MaxRectsTexturePlacer::new(config, args.heuristic.into()))?
+        }
+        Placer::Skyline => packer.pack(SkylineTexturePlacer::new(config, !args.no_waste_map))?,
+    };
+
+    std::fs::create_dir_all(&args.output_dir)?;
+    let texture_cache = TextureCache::new(0);
+    let extension = match args.format {
+        Format::Webp => export(&packed, WebpAtlasExporter::default(), &args, &texture_cache)?,
+        Format::Png => export(&packed, PngAtlasExporter::default(), &args, &texture_cache)?,
+        Format::Jpeg => export(&packed, JpegAtlasExporter::default(), &args, &texture_cache)?,
+        #[cfg(feature = "avif")]
+        Format::Avif => export(&packed, AvifAtlasExporter::default(), &args, &texture_cache)?,
+        #[cfg(feature = "ktx2")]
+        Format::Ktx2 => export(&packed, Ktx2AtlasExporter::default(), &args, &texture_cache)?,
+        #[cfg(feature = "dds")]
+        Format::Dds => export(&packed, DdsAtlasExporter::default(), &args, &texture_cache)?,
+    };
+
+    let manifest = File::create(args.output_dir.join("manifest.json"))?;
+    packed.write_manifest(&extension, BufWriter::new(manifest))?;
+
+    let remap_path = args.output_dir.join(if is_csv {
+        "uv_remap.csv"
+    } else {
+        "uv_remap.json"
+    });
+    let mut remap = BufWriter::new(File::create(remap_path)?);
+    if is_csv {
+        write_remap_csv(&packed, &polygons, &extension, &mut remap)?;
+    } else {
+        serde_json::to_writer_pretty(&mut remap, &remap_json(&packed, &polygons, &extension))?;
+    }
+    remap.flush()?;
+
+    println!(
+        "packed {} polygons into {} atlases in {}",
+        polygons.len(),
+        packed.atlas_ids().len(),
+        args.output_dir.display()
+    );
+    Ok(())
+}
+
+// Export every atlas, returning the extension of the files
+fn export<E: AtlasExporter>(
+    packed: &PackedAtlasProvider,
+    exporter: E,
+    args: &Args,
+    texture_cache: &TextureCache,
+) -> atlas_packer::error::Result<String> {
+    let extension = exporter.get_extension().to_string();
+    packed.export(&exporter, &args.output_dir, texture_cache)?;
+    Ok(extension)
+}
+
+fn parse_json(manifest: &str) -> Result<Vec<Polygon>, Box<dyn Error>> {
+    let Value::Array(entries) = serde_json::from_str(manifest)? else {
+        return Err("the JSON manifest must be an array of polygons".into());
+    };
+
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let field = |name: &str| {
+                entry
+                    .get(name)
+                    .ok_or_else(|| format!("polygon {} has no `{}`", i, name))
+            };
+            let id = field("id")?
+                .as_str()
+                .ok_or_else(|| format!("`id` of polygon {} must be a string", i))?;
+            let texture = field("texture")?
+                .as_str()
+                .ok_or_else(|| format!("`texture` of polygon {} must be a string", i))?;
+            let uv_coords = field("uv")?
+                .as_array()
+                .ok_or_else(|| format!("`uv` of polygon {} must be [[u, v], ...]", i))?
+                .iter()
+                .map(|uv| match uv.as_array().map(|uv| uv.as_slice()) {
+                    Some([Value::Number(u), Value::Number(v)]) => {
+                        Ok((u.as_f64().unwrap(), v.as_f64().unwrap()))
+                    }
+                    _ => Err(format!("`uv` of polygon {} must be [[u, v], ...]", i)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let downsample_factor = match entry.get("downsample_factor") {
+                Some(factor) => factor.as_f64().ok_or_else(|| {
+                    format!("`downsample_factor` of polygon {} must be a number", i)
+                })? as f32,
+                None => 1.0,
+            };
+
+            Ok(Polygon {
+                id: id.to_string(),
+                texture: PathBuf::from(texture),
+                uv_coords,
+                downsample_factor,
+            })
+        })
+        .collect()
+}
+
+fn parse_csv(manifest: &str) -> Result<Vec<Polygon>, Box<dyn Error>> {
+    let mut lines = manifest
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or("the CSV manifest is empty")?;
+    let header: Vec<&str> = header.split(',').map(str::trim).collect();
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|column| *column == name)
+            .ok_or_else(|| format!("the CSV manifest has no `{}` column", name))
+    };
+    let (id_column, texture_column, uv_column) = (column("id")?, column("texture")?, column("uv")?);
+    let factor_column = column("downsample_factor").ok();
+
+    lines
+        .map(|(i, line)| {
+            let line_number = i + 1;
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            if fields.len() != header.len() {
+                return Err(format!(
+                    "line {} has {} fields instead of {}",
+                    line_number,
+                    fields.len(),
+                    header.len()
+                )
+                .into());
+            }
+
+            let values = fields[uv_column]
+                .split_whitespace()
+                .map(str::parse::<f64>)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("invalid UV on line {}: {}", line_number, e))?;
+            if values.len() % 2 != 0 {
+                return Err(format!("odd number of UV values on line {}", line_number).into());
+            }
+            let downsample_factor = match factor_column.map(|column| fields[column]) {
+                Some(factor) if !factor.is_empty() => factor.parse().map_err(|e| {
+                    format!("invalid downsample factor on line {}: {}", line_number, e)
+                })?,
+                _ => 1.0,
+            };
+
+            Ok(Polygon {
+                id: fields[id_column].to_string(),
+                texture: PathBuf::from(fields[texture_column]),
+                uv_coords: values.chunks(2).map(|uv| (uv[0], uv[1])).collect(),
+                downsample_factor,
+            })
+        })
+        .collect()
+}
+
+fn remap_json(packed: &PackedAtlasProvider, polygons: &[Polygon], extension: &str) -> Value {
+    let entries = polygons
+        .iter()
+        .filter_map(|polygon| packed.get_texture_info(&polygon.id))
+        .map(|placed| {
+            json!({
+                "id": placed.polygon_id,
+                "atlas": placed.atlas_id,
+                "file": format!("{}.{}", placed.atlas_id, extension),
+                "uv": placed.placed_uv_coords,
+            })
+        })
+        .collect::<Vec<_>>();
+    Value::Array(entries)
+}
+
+fn write_remap_csv(
+    packed: &PackedAtlasProvider,
+    polygons: &[Polygon],
+    extension: &str,
+    writer: &mut impl Write,
+) -> std::io::Result<()> {
+    writeln!(writer, "id,atlas,file,uv")?;
+    for placed in polygons
+        .iter()
+        .filter_map(|polygon| packed.get_texture_info(&polygon.id))
+    {
+        let uv = placed
+            .placed_uv_coords
+            .iter()
+            .map(|(u, v)| format!("{} {}", u, v))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(
+            writer,
+            "{},{},{}.{},{}",
+            placed.polygon_id, placed.atlas_id, placed.atlas_id, extension, uv
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_manifest() {
+        let expected = vec![
+            Polygon {
+                id: "a".to_string(),
+                texture: PathBuf::from("textures/a.png"),
+                uv_coords: vec![(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)],
+                downsample_factor: 0.5,
+            },
+            Polygon {
+                id: "b".to_string(),
+                texture: PathBuf::from("b.png"),
+                uv_coords: vec![(0.25, 0.25), (0.75, 0.25), (0.75, 0.75)],
+                downsample_factor: 1.0,
+            },
+        ];
+
+        let json = r#"[
+            {"id": "a", "texture": "textures/a.png", "uv": [[0, 0], [1, 0], [0.5, 1]], "downsample_factor": 0.5},
+            {"id": "b", "texture": "b.png", "uv": [[0.25, 0.25], [0.75, 0.25], [0.75, 0.75]]}
+        ]"#;
+        assert_eq!(parse_json(json).unwrap(), expected);
+
+        let csv = "id,texture,downsample_factor,uv\n\
+                   a,textures/a.png,0.5,0 0 1 0 0.5 1\n\
+                   \n\
+                   b,b.png,,0.25 0.25 0.75 0.25 0.75 0.75\n";
+        assert_eq!(parse_csv(csv).unwrap(), expected);
+
+        assert!(parse_json(r#"[{"id": "a", "texture": "a.png", "uv": [[0]]}]"#).is_err());
+        assert!(parse_csv("id,texture,uv\na,a.png,0 0 1\n").is_err());
+        assert!(parse_csv("id,uv\na,0 0\n").is_err());
+    }
+}
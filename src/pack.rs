@@ -1,35 +1,221 @@
+use std::cmp::Reverse;
+use std::collections::VecDeque;
+use std::io::Write;
 use std::path::Path;
 
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 use rayon::prelude::*;
 use rstar::{RTree, RTreeObject, AABB};
+use serde_json::json;
 
 use crate::disjoint_set::DisjointSet;
+use crate::error::{AtlasPackerError, Result};
 use crate::export::AtlasExporter;
 use crate::place::{PlacedTextureGeometry, PlacedUVPolygon, TexturePlacer};
 use crate::texture::cache::TextureCache;
-use crate::texture::{ChildUVPolygon, ClusterBoundingTexture, PolygonMappedTexture};
+use crate::texture::{
+    ChildUVPolygon, ClusterBoundingTexture, DownsampleFactor, PolygonMappedTexture, TextureSource,
+};
 use crate::{AtlasID, ClusterID, PolygonID};
 pub type Atlas = Vec<PlacedTextureGeometry>;
 
-pub struct AtlasPacker {
-    textures: HashMap<PolygonID, PolygonMappedTexture>,
+/// Order in which clusters are handed to the placer.
+/// Ties are broken by the order in which the textures were added, so packing is reproducible.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PackingOrder {
+    /// Largest area first
+    #[default]
+    AreaDescending,
+    /// Longest side first
+    MaxSideDescending,
+    /// Largest perimeter first
+    PerimeterDescending,
+    /// Tallest first, then widest
+    HeightDescending,
+    /// Order in which the textures were added
+    InsertionOrder,
+}
+
+impl PackingOrder {
+    // Lower keys are placed first
+    fn sort_key(&self, width: u32, height: u32) -> Reverse<(u64, u64)> {
+        let (width, height) = (width as u64, height as u64);
+        match self {
+            PackingOrder::AreaDescending => Reverse((width * height, 0)),
+            PackingOrder::MaxSideDescending => Reverse((width.max(height), width.min(height))),
+            PackingOrder::PerimeterDescending => Reverse((2 * (width + height), 0)),
+            PackingOrder::HeightDescending => Reverse((height, width)),
+            PackingOrder::InsertionOrder => Reverse((0, 0)),
+        }
+    }
+}
+
+/// How to handle a cluster that does not fit even in an empty atlas
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizePolicy {
+    /// Return [`AtlasPackerError::TextureTooLarge`]
+    #[default]
+    Error,
+    /// Lower the downsample factor of the cluster until it fits
+    Downsample,
+    /// Place each member polygon as its own cluster; single polygons that still do not fit are downsampled
+    Split,
+    /// Put the cluster alone on an extra atlas that has exactly the size of the cluster
+    DedicatedPage,
+}
+
+/// Which atlases are tried when placing a cluster
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PageSelection {
+    /// Only the atlas being filled; it is closed as soon as a cluster does not fit
+    #[default]
+    CurrentPage,
+    /// Every atlas is kept open, and the first one in which the cluster fits is used
+    FirstFit,
+    /// Every atlas is kept open, and the fullest one in which the cluster fits is used
+    BestFit,
+}
+
+/// When two polygons on the same image are clustered into a single crop
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OverlapTest {
+    /// Their bounding boxes overlap
+    #[default]
+    BoundingBox,
+    /// The polygons themselves overlap, or come within `tolerance` pixels of each other
+    Polygon { tolerance: f64 },
+}
+
+/// Limits on merging polygons into a cluster, so that a chain of small overlapping crops
+/// does not end up as a huge crop that is mostly unused pixels.
+/// A merge that would exceed a limit is refused, and the polygons are cropped separately.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterLimits {
+    /// Minimum ratio of the sum of the member crop areas to the area of the merged crop
+    pub min_fill_ratio: f64,
+    /// Maximum width and height of a merged cluster after downsampling, as a fraction of the atlas size
+    pub max_size_ratio: f64,
 }
 
-impl Default for AtlasPacker {
+impl Default for ClusterLimits {
+    // No limits
     fn default() -> Self {
-        Self {
-            textures: HashMap::new(),
+        ClusterLimits {
+            min_fill_ratio: 0.0,
+            max_size_ratio: 1.0,
         }
     }
 }
 
+impl ClusterLimits {
+    fn allow(&self, extent: &ClusterExtent, page_size: (u32, u32)) -> bool {
+        let (width, height) = (extent.max_x - extent.min_x, extent.max_y - extent.min_y);
+        let crop_area = (width as u64 * height as u64).max(1);
+        let fill_ratio = extent.member_area as f64 / crop_area as f64;
+
+        let factor = extent.downsample_factor as f64;
+        fill_ratio >= self.min_fill_ratio
+            && width as f64 * factor <= page_size.0 as f64 * self.max_size_ratio
+            && height as f64 * factor <= page_size.1 as f64 * self.max_size_ratio
+    }
+}
+
+/// Rounding applied to the dimensions of a shrunk atlas
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SizeRounding {
+    /// Exactly the used extent
+    #[default]
+    None,
+    /// The next power of two
+    PowerOfTwo,
+    /// The next multiple of the value (e.g. 4 for block-compressed formats)
+    MultipleOf(u32),
+}
+
+impl SizeRounding {
+    fn round(&self, size: u32) -> u32 {
+        let size = size.max(1);
+        match self {
+            SizeRounding::None => size,
+            SizeRounding::PowerOfTwo => size.next_power_of_two(),
+            SizeRounding::MultipleOf(n) => size.next_multiple_of((*n).max(1)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AtlasPackerConfig {
+    pub order: PackingOrder,
+    pub oversize_policy: OversizePolicy,
+    pub page_selection: PageSelection,
+    pub overlap_test: OverlapTest,
+    /// No limits on the clusters when `None`
+    pub cluster_limits: Option<ClusterLimits>,
+}
+
+#[derive(Default)]
+pub struct AtlasPacker {
+    config: AtlasPackerConfig,
+    textures: HashMap<PolygonID, PolygonMappedTexture>,
+    // Polygon IDs in the order they were added
+    polygon_ids: Vec<PolygonID>,
+}
+
+// An atlas that can still receive clusters
 #[derive(Clone)]
+struct OpenPage<P: TexturePlacer> {
+    atlas_id: AtlasID,
+    placer: P,
+    // Area occupied by the placed clusters, including padding
+    used_area: u64,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(super) struct Cluster {
     pub bounding_texture: ClusterBoundingTexture,
     pub uv_polygons: Vec<(PolygonID, ChildUVPolygon)>,
 }
 
+// Crop of a cluster being built, to evaluate a merge against the cluster limits
+#[derive(Clone, Copy)]
+struct ClusterExtent {
+    min_x: u32,
+    min_y: u32,
+    max_x: u32,
+    max_y: u32,
+    // Sum of the crop areas of the members
+    member_area: u64,
+    downsample_factor: f32,
+}
+
+impl ClusterExtent {
+    fn new(texture: &PolygonMappedTexture) -> Self {
+        let bounding_texture = ClusterBoundingTexture::new(texture);
+        let (x, y) = bounding_texture.crop_origin();
+        let (width, height) = (bounding_texture.crop_width, bounding_texture.crop_height);
+        ClusterExtent {
+            min_x: x,
+            min_y: y,
+            max_x: x + width,
+            max_y: y + height,
+            member_area: width as u64 * height as u64,
+            downsample_factor: bounding_texture.downsample_factor.value(),
+        }
+    }
+
+    fn merge(&self, other: &Self) -> Self {
+        ClusterExtent {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+            member_area: self.member_area + other.member_area,
+            downsample_factor: self.downsample_factor.max(other.downsample_factor),
+        }
+    }
+}
+
 struct Rectangle {
     index: usize,
     min_x: f32,
@@ -47,15 +233,43 @@ impl RTreeObject for Rectangle {
 }
 
 impl AtlasPacker {
+    pub fn new(config: AtlasPackerConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &AtlasPackerConfig {
+        &self.config
+    }
+
     pub fn add_texture(&mut self, polygon_id: PolygonID, texture: PolygonMappedTexture) {
-        self.textures.insert(polygon_id, texture);
+        if self.textures.insert(polygon_id.clone(), texture).is_none() {
+            self.polygon_ids.push(polygon_id);
+        }
     }
 
-    fn create_clusters(&self) -> HashMap<ClusterID, Cluster> {
-        let polygon_ids: Vec<PolygonID> = self.textures.keys().cloned().collect();
+    // Clusters of the polygons from `start` on, in the order their first polygon was added.
+    // Cluster IDs are indices into all the polygons plus `id_offset`, so that they are unique across calls.
+    fn create_clusters(
+        &self,
+        start: usize,
+        id_offset: usize,
+        page_size: (u32, u32),
+    ) -> Vec<(ClusterID, Cluster)> {
+        let polygon_ids = &self.polygon_ids[start..];
 
         let mut rtree = RTree::new();
         let mut disjoint_set = DisjointSet::new(polygon_ids.len());
+        // Extent of the cluster of each root, only tracked when the clusters are limited
+        let mut extents = match self.config.cluster_limits {
+            Some(_) => polygon_ids
+                .iter()
+                .map(|polygon_id| ClusterExtent::new(&self.textures[polygon_id]))
+                .collect(),
+            None => Vec::new(),
+        };
 
         for (i, polygon_id) in polygon_ids.iter().enumerate() {
             let texture = self.textures.get(polygon_id).unwrap();
@@ -69,11 +283,16 @@ impl AtlasPacker {
             };
             rtree.insert(texture_with_index);
         }
+        let tolerance = match self.config.overlap_test {
+            OverlapTest::BoundingBox => 0.0,
+            OverlapTest::Polygon { tolerance } => tolerance,
+        };
         for (i, polygon_id) in polygon_ids.iter().enumerate() {
             let texture = self.textures.get(polygon_id).unwrap();
+            let (min_x, min_y, max_x, max_y) = texture.bbox();
             let bbox = AABB::from_corners(
-                [texture.bbox().0 as f32, texture.bbox().1 as f32],
-                [texture.bbox().2 as f32, texture.bbox().3 as f32],
+                [(min_x - tolerance) as f32, (min_y - tolerance) as f32],
+                [(max_x + tolerance) as f32, (max_y + tolerance) as f32],
             );
 
             // Only items with the same texture and overlapping areas will be searched
@@ -81,30 +300,54 @@ impl AtlasPacker {
                 .locate_in_envelope_intersecting(&bbox)
                 .filter(|target| {
                     let target_texture = self.textures.get(&polygon_ids[target.index]).unwrap();
-                    texture.image_path == target_texture.image_path
+                    match self.config.overlap_test {
+                        OverlapTest::BoundingBox => {
+                            texture.source.id() == target_texture.source.id()
+                        }
+                        OverlapTest::Polygon { tolerance } => {
+                            texture.polygon_overlaps(target_texture, tolerance)
+                        }
+                    }
                 });
 
             for j in hit {
-                if i < j.index {
+                if i >= j.index {
+                    continue;
+                }
+                let Some(limits) = self.config.cluster_limits else {
+                    disjoint_set.unite(i, j.index);
+                    continue;
+                };
+
+                let (root_i, root_j) = (disjoint_set.root(i), disjoint_set.root(j.index));
+                if root_i == root_j {
+                    continue;
+                }
+                let merged = extents[root_i].merge(&extents[root_j]);
+                if limits.allow(&merged, page_size) {
                     disjoint_set.unite(i, j.index);
+                    extents[disjoint_set.root(i)] = merged;
                 }
             }
         }
         disjoint_set.compress();
 
-        let clustered_polygon_ids: HashMap<ClusterID, Vec<PolygonID>> = {
-            let mut clustered_polygon_ids = HashMap::new();
+        let clustered_polygon_ids: Vec<(ClusterID, Vec<PolygonID>)> = {
+            let mut clustered_polygon_ids: Vec<(ClusterID, Vec<PolygonID>)> = Vec::new();
+            let mut cluster_indices: HashMap<usize, usize> = HashMap::new();
             for (i, polygon_id) in polygon_ids.iter().enumerate() {
-                let cluster_id = disjoint_set.root(i).to_string();
-                clustered_polygon_ids
-                    .entry(cluster_id)
-                    .or_insert_with(Vec::new)
-                    .push(polygon_id.clone());
+                let root = disjoint_set.root(i);
+                let index = *cluster_indices.entry(root).or_insert_with(|| {
+                    clustered_polygon_ids
+                        .push(((id_offset + start + root).to_string(), Vec::new()));
+                    clustered_polygon_ids.len() - 1
+                });
+                clustered_polygon_ids[index].1.push(polygon_id.clone());
             }
             clustered_polygon_ids
         };
 
-        let cluster_map: HashMap<ClusterID, Cluster> = clustered_polygon_ids
+        clustered_polygon_ids
             .iter()
             .filter_map(|(cluster_id, polygon_ids)| {
                 let bounding_texture = polygon_ids.iter().fold(
@@ -134,98 +377,1432 @@ impl AtlasPacker {
                     },
                 ))
             })
+            .collect::<Vec<_>>()
+    }
+
+    pub fn pack<P: TexturePlacer + Clone>(self, placer: P) -> Result<PackedAtlasProvider> {
+        let mut packer = self.into_incremental(placer);
+        packer.pack_pending()?;
+        Ok(packer.into_provider())
+    }
+
+    /// Keep the state of the placers after packing, so that more textures can be packed later.
+    /// The textures added so far are packed by the first call to [`IncrementalPacker::pack_pending`].
+    pub fn into_incremental<P: TexturePlacer + Clone>(self, placer: P) -> IncrementalPacker<P> {
+        IncrementalPacker {
+            packer: self,
+            placer,
+            packed_count: 0,
+            cluster_id_offset: 0,
+            open_pages: Vec::new(),
+            next_atlas_id: 0,
+            provider: PackedAtlasProvider::empty(),
+        }
+    }
+
+    // Index of the open page in which the texture is placed, if any
+    fn find_page<P: TexturePlacer>(
+        &self,
+        open_pages: &[OpenPage<P>],
+        texture: &ClusterBoundingTexture,
+    ) -> Option<usize> {
+        let mut candidates = open_pages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| page.placer.can_place(texture));
+        match self.config.page_selection {
+            PageSelection::CurrentPage | PageSelection::FirstFit => candidates.next(),
+            // The fullest page; the earliest one wins a tie
+            PageSelection::BestFit => candidates.min_by_key(|(_, page)| Reverse(page.used_area)),
+        }
+        .map(|(page_index, _)| page_index)
+    }
+
+    // Break a cluster up into one cluster per member polygon
+    fn split_cluster(
+        &self,
+        cluster_id: &ClusterID,
+        cluster: &Cluster,
+    ) -> Vec<(ClusterID, Cluster)> {
+        cluster
+            .uv_polygons
+            .iter()
+            .enumerate()
+            .map(|(i, (polygon_id, _))| {
+                let texture = self.textures.get(polygon_id).unwrap();
+                let bounding_texture = ClusterBoundingTexture::new(texture);
+                let uv_polygons = vec![(polygon_id.clone(), bounding_texture.get_child(texture))];
+                (
+                    format!("{}-{}", cluster_id, i),
+                    Cluster {
+                        bounding_texture,
+                        uv_polygons,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// Packer that keeps the state of its placers, so that textures can be added after packing.
+/// Clusters that have been placed never move; later textures go into the free space of the open atlases or into new ones.
+pub struct IncrementalPacker<P: TexturePlacer + Clone> {
+    packer: AtlasPacker,
+    placer: P,
+    // Number of the added polygons that have been packed
+    packed_count: usize,
+    // Added to the IDs of new clusters, so that they differ from those of a resumed pack
+    cluster_id_offset: usize,
+    open_pages: Vec<OpenPage<P>>,
+    next_atlas_id: AtlasID,
+    provider: PackedAtlasProvider,
+}
+
+impl<P: TexturePlacer + Clone> IncrementalPacker<P> {
+    /// Add a texture to be packed by the next call to [`IncrementalPacker::pack_pending`].
+    /// Polygons that have already been packed are left as they are.
+    pub fn add_texture(&mut self, polygon_id: PolygonID, texture: PolygonMappedTexture) {
+        if self.packer.textures.contains_key(&polygon_id)
+            || self
+                .provider
+                .placed_uv_polygon_map
+                .contains_key(&polygon_id)
+        {
+            return;
+        }
+        self.packer.add_texture(polygon_id, texture);
+    }
+
+    /// Number of the added polygons that have not been packed yet
+    pub fn pending_count(&self) -> usize {
+        self.packer.polygon_ids.len() - self.packed_count
+    }
+
+    /// Pack the textures added since the last call.
+    /// The new polygons are clustered among themselves, not with the polygons that have already been packed.
+    /// If any of their clusters cannot be placed, none of them is, and they stay pending.
+    pub fn pack_pending(&mut self) -> Result<()> {
+        let page_size = (self.placer.config().width, self.placer.config().height);
+        let clusters =
+            self.packer
+                .create_clusters(self.packed_count, self.cluster_id_offset, page_size);
+        self.place_clusters(clusters)?;
+        self.packed_count = self.packer.polygon_ids.len();
+        Ok(())
+    }
+
+    /// Remove polygons, packed or not.
+    /// The space of the clusters that are left empty is freed for later textures, and nothing else moves.
+    pub fn remove_polygons(&mut self, polygon_ids: &[PolygonID]) {
+        for placed in self.provider.remove_polygons(polygon_ids) {
+            let Some(page) = self
+                .open_pages
+                .iter_mut()
+                .find(|page| page.atlas_id == placed.atlas_id)
+            else {
+                continue;
+            };
+            page.placer.free_texture(&placed);
+            let config = page.placer.config();
+            page.used_area -=
+                config.footprint(placed.width) as u64 * config.footprint(placed.height) as u64;
+        }
+
+        let removed = polygon_ids.iter().collect::<HashSet<_>>();
+        for polygon_id in polygon_ids {
+            self.packer.textures.remove(polygon_id);
+        }
+        let removed_packed = self.packer.polygon_ids[..self.packed_count]
+            .iter()
+            .filter(|polygon_id| removed.contains(polygon_id))
+            .count();
+        self.packer
+            .polygon_ids
+            .retain(|polygon_id| !removed.contains(polygon_id));
+        self.packed_count -= removed_packed;
+        // Cluster IDs are derived from the indices of the polygons, which the removed ones no longer take up
+        self.cluster_id_offset += removed_packed;
+    }
+
+    /// Repack every cluster from scratch into as few atlases as possible.
+    /// Clusters keep their contents, but move, and the atlases are renumbered from 0.
+    /// If a cluster cannot be placed, the packing is left as it was.
+    pub fn compact(&mut self) -> Result<()> {
+        // Placed in the order their first polygon was added, as in the first packing
+        let polygon_indices = self
+            .packer
+            .polygon_ids
+            .iter()
+            .enumerate()
+            .map(|(i, polygon_id)| (polygon_id.clone(), i))
             .collect::<HashMap<_, _>>();
+        let mut clusters = self
+            .provider
+            .clusters
+            .iter()
+            .map(|(cluster_id, cluster)| (cluster_id.clone(), cluster.clone()))
+            .collect::<Vec<_>>();
+        clusters.sort_by_key(|(_, cluster)| {
+            cluster
+                .uv_polygons
+                .iter()
+                .map(|(polygon_id, _)| polygon_indices[polygon_id])
+                .min()
+        });
 
-        cluster_map
+        let open_pages = std::mem::take(&mut self.open_pages);
+        let next_atlas_id = std::mem::replace(&mut self.next_atlas_id, 0);
+        let provider = std::mem::replace(&mut self.provider, PackedAtlasProvider::empty());
+        if let Err(error) = self.place_clusters(clusters) {
+            self.open_pages = open_pages;
+            self.next_atlas_id = next_atlas_id;
+            self.provider = provider;
+            return Err(error);
+        }
+        Ok(())
     }
 
-    pub fn pack<P: TexturePlacer>(self, mut placer: P) -> PackedAtlasProvider {
-        let mut current_atlas: Atlas = Vec::new();
-        let mut atlases: HashMap<AtlasID, Atlas> = HashMap::new();
+    // Place the clusters, or leave everything as it was if any of them cannot be placed
+    fn place_clusters(&mut self, clusters: Vec<(ClusterID, Cluster)>) -> Result<()> {
+        let open_pages = self.open_pages.clone();
+        let next_atlas_id = self.next_atlas_id;
+        let atlas_lengths = self
+            .provider
+            .atlases
+            .iter()
+            .map(|(atlas_id, atlas)| (*atlas_id, atlas.len()))
+            .collect::<HashMap<_, _>>();
+
+        let result = self.try_place_clusters(clusters);
+        if result.is_err() {
+            self.open_pages = open_pages;
+            self.next_atlas_id = next_atlas_id;
 
-        let clusters = self.create_clusters();
-        let mut placed_uv_polygon_map: HashMap<PolygonID, PlacedUVPolygon> = HashMap::new();
-        for (cluster_id, cluster) in clusters.iter() {
-            if !placer.can_place(&cluster.bounding_texture) {
-                let current_atlas_id = atlases.len();
-                atlases.insert(current_atlas_id, current_atlas.clone());
-                current_atlas.clear();
-                placer.reset_param();
+            // New clusters are appended to the atlases, or are on new ones
+            let mut placed = Vec::new();
+            self.provider
+                .atlases
+                .retain(|atlas_id, atlas| match atlas_lengths.get(atlas_id) {
+                    Some(&length) => {
+                        placed.extend(atlas.drain(length..));
+                        true
+                    }
+                    None => {
+                        placed.append(atlas);
+                        false
+                    }
+                });
+            self.provider
+                .atlas_dimensions
+                .retain(|atlas_id, _| atlas_lengths.contains_key(atlas_id));
+            for placed in placed {
+                let Some(cluster) = self.provider.clusters.remove(&placed.cluster_id) else {
+                    continue;
+                };
+                for (polygon_id, _) in cluster.uv_polygons {
+                    self.provider.placed_uv_polygon_map.remove(&polygon_id);
+                }
             }
+        }
+        result
+    }
+
+    fn try_place_clusters(&mut self, mut clusters: Vec<(ClusterID, Cluster)>) -> Result<()> {
+        let page_size = (self.placer.config().width, self.placer.config().height);
+
+        clusters.sort_by_cached_key(|(_, cluster)| {
+            let (width, height) = self.placer.scale_dimensions(
+                cluster.bounding_texture.crop_width,
+                cluster.bounding_texture.crop_height,
+                cluster.bounding_texture.downsample_factor.value(),
+            );
+            self.packer.config.order.sort_key(width, height)
+        });
 
-            let current_atlas_id = atlases.len();
+        let mut queue: VecDeque<(ClusterID, Cluster)> = clusters.into();
+        while let Some((cluster_id, mut cluster)) = queue.pop_front() {
+            let page_index = match self
+                .packer
+                .find_page(&self.open_pages, &cluster.bounding_texture)
+            {
+                Some(page_index) => page_index,
+                None => {
+                    let mut new_placer = self.placer.clone();
+                    new_placer.reset_param();
 
-            let (placed_texture, placed_uv_polygons) = placer.place_texture(
+                    // The cluster does not fit even in an empty atlas
+                    if !new_placer.can_place(&cluster.bounding_texture) {
+                        match self.packer.config.oversize_policy {
+                            OversizePolicy::Error => {
+                                return Err(too_large_error(&self.placer, cluster_id, &cluster));
+                            }
+                            OversizePolicy::Split if cluster.uv_polygons.len() > 1 => {
+                                for split in self
+                                    .packer
+                                    .split_cluster(&cluster_id, &cluster)
+                                    .into_iter()
+                                    .rev()
+                                {
+                                    queue.push_front(split);
+                                }
+                            }
+                            OversizePolicy::DedicatedPage => {
+                                let atlas_id = self.next_atlas_id;
+                                self.next_atlas_id += 1;
+                                let (placed_texture, placed_uv_polygons, page_size) =
+                                    place_on_dedicated_page(
+                                        &self.placer,
+                                        &cluster,
+                                        cluster_id.clone(),
+                                        atlas_id,
+                                    );
+                                self.provider.atlas_dimensions.insert(atlas_id, page_size);
+                                self.provider.atlases.insert(atlas_id, vec![placed_texture]);
+                                for placed_uv_polygon in placed_uv_polygons {
+                                    self.provider.placed_uv_polygon_map.insert(
+                                        placed_uv_polygon.polygon_id.clone(),
+                                        placed_uv_polygon,
+                                    );
+                                }
+                                self.provider.clusters.insert(cluster_id, cluster);
+                            }
+                            OversizePolicy::Downsample | OversizePolicy::Split => {
+                                cluster.bounding_texture.downsample_factor =
+                                    fitting_downsample_factor(
+                                        &self.placer,
+                                        &cluster.bounding_texture,
+                                    )?;
+                                if !new_placer.can_place(&cluster.bounding_texture) {
+                                    return Err(too_large_error(
+                                        &self.placer,
+                                        cluster_id,
+                                        &cluster,
+                                    ));
+                                }
+                                // Retry, as the downsampled cluster may fit in an open page
+                                queue.push_front((cluster_id, cluster));
+                            }
+                        }
+                        continue;
+                    }
+
+                    let atlas_id = self.next_atlas_id;
+                    self.next_atlas_id += 1;
+                    self.provider.atlases.insert(atlas_id, Vec::new());
+                    self.provider.atlas_dimensions.insert(atlas_id, page_size);
+                    self.open_pages.push(OpenPage {
+                        atlas_id,
+                        placer: new_placer,
+                        used_area: 0,
+                    });
+                    self.close_unrevisited_pages();
+                    self.open_pages.len() - 1
+                }
+            };
+
+            let page = &mut self.open_pages[page_index];
+            let (placed_texture, placed_uv_polygons) = page.placer.place_texture(
                 cluster.bounding_texture.clone(),
                 cluster.uv_polygons.clone(),
                 cluster_id.clone(),
-                current_atlas_id,
-            );
+                page.atlas_id,
+            )?;
 
-            current_atlas.push(placed_texture.clone());
+            let config = page.placer.config();
+            page.used_area += config.footprint(placed_texture.width) as u64
+                * config.footprint(placed_texture.height) as u64;
+            self.provider
+                .atlases
+                .get_mut(&page.atlas_id)
+                .unwrap()
+                .push(placed_texture);
 
-            let polygon_ids = cluster
-                .uv_polygons
-                .iter()
-                .map(|(id, _)| id)
-                .collect::<Vec<_>>();
-
-            for (polygon_id, placed_uv_polygon) in polygon_ids.iter().zip(placed_uv_polygons) {
+            for ((polygon_id, _), placed_uv_polygon) in
+                cluster.uv_polygons.iter().zip(placed_uv_polygons)
+            {
                 if let Some(placed_uv_polygon) = placed_uv_polygon {
-                    placed_uv_polygon_map.insert((*polygon_id).clone(), placed_uv_polygon.clone());
+                    self.provider
+                        .placed_uv_polygon_map
+                        .insert(polygon_id.clone(), placed_uv_polygon);
                 }
             }
+
+            self.provider.clusters.insert(cluster_id, cluster);
         }
 
-        // treat the last atlas
-        if !current_atlas.is_empty() {
-            let current_atlas_id = atlases.len();
+        Ok(())
+    }
+
+    /// Result of the packing so far
+    pub fn provider(&self) -> &PackedAtlasProvider {
+        &self.provider
+    }
+
+    pub fn into_provider(self) -> PackedAtlasProvider {
+        self.provider
+    }
+
+    /// Continue packing into an existing pack, such as one returned by [`AtlasPacker::pack`] or deserialized.
+    /// `placer` must have the config the pack was made with. Its state is rebuilt from the placed clusters,
+    /// and the atlases of its size are reopened as the page selection allows; atlases of another size,
+    /// such as dedicated or shrunk ones, stay closed.
+    /// The packed polygons are not clustered with the ones added later, so their textures are not needed.
+    pub fn resume(config: AtlasPackerConfig, placer: P, provider: PackedAtlasProvider) -> Self {
+        let page_size = (placer.config().width, placer.config().height);
+        let atlas_ids = provider.atlas_ids();
 
-            atlases.insert(current_atlas_id, current_atlas.clone());
-            current_atlas.clear();
+        // Cluster IDs are an index, optionally followed by the index of a split member
+        let cluster_id_offset = provider
+            .clusters
+            .keys()
+            .filter_map(|cluster_id| cluster_id.split('-').next()?.parse::<usize>().ok())
+            .max()
+            .map_or(0, |index| index + 1);
+
+        let mut packer = AtlasPacker::new(config);
+        let mut open_pages = Vec::new();
+        for atlas_id in &atlas_ids {
+            let atlas = &provider.atlases[atlas_id];
+            for cluster in atlas
+                .iter()
+                .filter_map(|placed| provider.clusters.get(&placed.cluster_id))
+            {
+                for (polygon_id, _) in &cluster.uv_polygons {
+                    packer.polygon_ids.push(polygon_id.clone());
+                }
+            }
+            if provider.atlas_dimensions[atlas_id] != page_size {
+                continue;
+            }
+
+            let mut page = OpenPage {
+                atlas_id: *atlas_id,
+                placer: placer.clone(),
+                used_area: 0,
+            };
+            page.placer.reset_param();
+            for placed in atlas {
+                page.placer.occupy_texture(placed);
+                let config = page.placer.config();
+                page.used_area +=
+                    config.footprint(placed.width) as u64 * config.footprint(placed.height) as u64;
+            }
+            open_pages.push(page);
         }
 
-        PackedAtlasProvider {
-            clusters,
-            atlases,
-            placed_uv_polygon_map,
+        let mut packer = IncrementalPacker {
+            packed_count: packer.polygon_ids.len(),
+            packer,
+            placer,
+            cluster_id_offset,
+            open_pages,
+            next_atlas_id: atlas_ids.last().map_or(0, |atlas_id| atlas_id + 1),
+            provider,
+        };
+        packer.close_unrevisited_pages();
+        packer
+    }
+
+    // Only the last page is kept open when earlier pages are not revisited
+    fn close_unrevisited_pages(&mut self) {
+        if self.packer.config.page_selection == PageSelection::CurrentPage {
+            let last = self.open_pages.len().saturating_sub(1);
+            self.open_pages.drain(..last);
         }
     }
 }
 
+fn too_large_error<P: TexturePlacer>(
+    placer: &P,
+    cluster_id: ClusterID,
+    cluster: &Cluster,
+) -> AtlasPackerError {
+    let (width, height) = placer.scale_dimensions(
+        cluster.bounding_texture.crop_width,
+        cluster.bounding_texture.crop_height,
+        cluster.bounding_texture.downsample_factor.value(),
+    );
+    AtlasPackerError::TextureTooLarge {
+        cluster_id,
+        width,
+        height,
+    }
+}
+
+// The largest downsample factor with which the texture fits in an empty atlas
+fn fitting_downsample_factor<P: TexturePlacer>(
+    placer: &P,
+    texture: &ClusterBoundingTexture,
+) -> Result<DownsampleFactor> {
+    let config = placer.config();
+    // Rounded down so that the aligned footprint still fits
+    let available_length = |length: u32| {
+        let alignment = config.block_alignment.max(1);
+        (length.saturating_sub(config.padding_offset()) / alignment * alignment) as f32
+    };
+    let available_width = available_length(config.width);
+    let available_height = available_length(config.height);
+    let (width, height) = (
+        texture.crop_width.max(1) as f32,
+        texture.crop_height.max(1) as f32,
+    );
+
+    let mut factor = (available_width / width).min(available_height / height);
+    if config.allow_rotation {
+        factor = factor.max((available_width / height).min(available_height / width));
+    }
+    DownsampleFactor::new(&factor.clamp(0.0, texture.downsample_factor.value()))
+}
+
+// Place the cluster alone on a page that has its size, rounded up to the block alignment.
+// Returns the size of the page as well.
+fn place_on_dedicated_page<P: TexturePlacer>(
+    placer: &P,
+    cluster: &Cluster,
+    cluster_id: ClusterID,
+    atlas_id: AtlasID,
+) -> (PlacedTextureGeometry, Vec<PlacedUVPolygon>, (u32, u32)) {
+    let (width, height) = placer.scale_dimensions(
+        cluster.bounding_texture.crop_width,
+        cluster.bounding_texture.crop_height,
+        cluster.bounding_texture.downsample_factor.value(),
+    );
+    let page_size = (placer.config().align(width), placer.config().align(height));
+    let scale_u = width as f64 / page_size.0 as f64;
+    let scale_v = height as f64 / page_size.1 as f64;
+    let placed_texture = PlacedTextureGeometry {
+        cluster_id: cluster_id.clone(),
+        atlas_id,
+        origin: (0, 0),
+        width,
+        height,
+        rotated: false,
+    };
+
+    // The cropped texture is at the top-left corner of the page, and V is measured from the bottom
+    let placed_uv_polygons = cluster
+        .uv_polygons
+        .iter()
+        .map(|(polygon_id, uv_polygon)| PlacedUVPolygon {
+            polygon_id: polygon_id.clone(),
+            cluster_id: cluster_id.clone(),
+            atlas_id,
+            placed_uv_coords: uv_polygon
+                .cropped_uv_coords
+                .iter()
+                .map(|(u, v)| (u * scale_u, 1.0 - (1.0 - v) * scale_v))
+                .collect(),
+        })
+        .collect();
+
+    (placed_texture, placed_uv_polygons, page_size)
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PackedAtlasProvider {
     atlases: HashMap<AtlasID, Atlas>,
+    // Width and height of each atlas
+    atlas_dimensions: HashMap<AtlasID, (u32, u32)>,
     clusters: HashMap<ClusterID, Cluster>,
     placed_uv_polygon_map: HashMap<PolygonID, PlacedUVPolygon>,
 }
 
 impl PackedAtlasProvider {
+    fn empty() -> Self {
+        PackedAtlasProvider {
+            atlases: HashMap::new(),
+            atlas_dimensions: HashMap::new(),
+            clusters: HashMap::new(),
+            placed_uv_polygon_map: HashMap::new(),
+        }
+    }
+
     pub fn export<E: AtlasExporter>(
         &self,
-        exporter: E,
+        exporter: &E,
         output_dir: &Path,
         texture_cache: &TextureCache,
-        width: u32,
-        height: u32,
-    ) {
-        self.atlases.par_iter().for_each(|(id, atlas)| {
+    ) -> Result<()> {
+        let textures = self.bounding_textures();
+
+        self.atlases.par_iter().try_for_each(|(id, atlas)| {
             let output_path = output_dir.join(id.to_string());
-            exporter.export(
-                atlas,
-                &self
-                    .clusters
+            let (width, height) = self.atlas_dimensions[id];
+            exporter.export(atlas, &textures, &output_path, texture_cache, width, height)
+        })
+    }
+
+    /// Encode every atlas into the bytes of an image file, keyed by the atlas ID
+    pub fn encode<E: AtlasExporter>(
+        &self,
+        exporter: &E,
+        texture_cache: &TextureCache,
+    ) -> Result<HashMap<AtlasID, Vec<u8>>> {
+        let textures = self.bounding_textures();
+
+        self.atlases
+            .par_iter()
+            .map(|(id, atlas)| {
+                let (width, height) = self.atlas_dimensions[id];
+                let encoded =
+                    exporter.encode_atlas(atlas, &textures, texture_cache, width, height)?;
+                Ok((*id, encoded))
+            })
+            .collect()
+    }
+
+    /// Write a single encoded atlas into `writer`
+    pub fn export_to_writer<E: AtlasExporter>(
+        &self,
+        atlas_id: AtlasID,
+        exporter: &E,
+        writer: &mut dyn Write,
+        texture_cache: &TextureCache,
+    ) -> Result<()> {
+        let atlas = self
+            .atlases
+            .get(&atlas_id)
+            .ok_or(AtlasPackerError::AtlasNotFound(atlas_id))?;
+        let (width, height) = self.atlas_dimensions[&atlas_id];
+        exporter.export_to_writer(
+            atlas,
+            &self.bounding_textures(),
+            writer,
+            texture_cache,
+            width,
+            height,
+        )
+    }
+
+    fn bounding_textures(&self) -> HashMap<ClusterID, ClusterBoundingTexture> {
+        self.clusters
+            .iter()
+            .map(|(id, cluster)| (id.clone(), cluster.bounding_texture.clone()))
+            .collect()
+    }
+
+    /// Remove polygons from the atlases. Clusters that are left empty are removed, and their placements returned.
+    /// The texels of the removed polygons are no longer drawn when masking, but nothing else moves,
+    /// and atlases that are left empty are kept.
+    pub fn remove_polygons(&mut self, polygon_ids: &[PolygonID]) -> Vec<PlacedTextureGeometry> {
+        let mut emptied_clusters = Vec::new();
+        for polygon_id in polygon_ids {
+            let Some(placed_uv_polygon) = self.placed_uv_polygon_map.remove(polygon_id) else {
+                continue;
+            };
+            let Some(cluster) = self.clusters.get_mut(&placed_uv_polygon.cluster_id) else {
+                continue;
+            };
+            // The member polygons of the bounding texture are in the same order
+            if let Some(index) = cluster
+                .uv_polygons
+                .iter()
+                .position(|(id, _)| id == polygon_id)
+            {
+                cluster.uv_polygons.remove(index);
+                cluster.bounding_texture.remove_polygon(index);
+            }
+            if cluster.uv_polygons.is_empty() {
+                self.clusters.remove(&placed_uv_polygon.cluster_id);
+                emptied_clusters.push((placed_uv_polygon.atlas_id, placed_uv_polygon.cluster_id));
+            }
+        }
+
+        emptied_clusters
+            .into_iter()
+            .filter_map(|(atlas_id, cluster_id)| {
+                let atlas = self.atlases.get_mut(&atlas_id)?;
+                let index = atlas
                     .iter()
-                    .map(|(id, cluster)| (id.clone(), cluster.bounding_texture.clone()))
-                    .collect::<HashMap<ClusterID, ClusterBoundingTexture>>(),
-                &output_path,
-                texture_cache,
-                width,
-                height,
-            );
-        });
+                    .position(|placed| placed.cluster_id == cluster_id)?;
+                Some(atlas.remove(index))
+            })
+            .collect()
+    }
+
+    /// Replace the source of every cluster with the same source ID,
+    /// e.g. to give back the in-memory images of a deserialized pack
+    pub fn attach_source(&mut self, source: &TextureSource) {
+        for cluster in self.clusters.values_mut() {
+            if cluster.bounding_texture.source.id() == source.id() {
+                cluster.bounding_texture.source = source.clone();
+            }
+        }
     }
 
     pub fn get_texture_info(&self, polygon_id: &PolygonID) -> Option<&PlacedUVPolygon> {
         self.placed_uv_polygon_map.get(polygon_id)
     }
+
+    pub fn atlas_ids(&self) -> Vec<AtlasID> {
+        let mut atlas_ids = self.atlases.keys().copied().collect::<Vec<_>>();
+        atlas_ids.sort();
+        atlas_ids
+    }
+
+    /// Width and height with which the atlas is exported
+    pub fn atlas_dimensions(&self, atlas_id: AtlasID) -> Option<(u32, u32)> {
+        self.atlas_dimensions.get(&atlas_id).copied()
+    }
+
+    /// Width and height of the area actually covered by the placed clusters
+    pub fn used_extent(&self, atlas_id: AtlasID) -> Option<(u32, u32)> {
+        let atlas = self.atlases.get(&atlas_id)?;
+        Some(atlas.iter().fold((0, 0), |(width, height), placed| {
+            (
+                width.max(placed.origin.0 + placed.width),
+                height.max(placed.origin.1 + placed.height),
+            )
+        }))
+    }
+
+    /// Crop every atlas to its used extent, rounded up as specified.
+    /// The atlases never grow, and the UV coordinates are renormalized for the new sizes.
+    pub fn shrink_to_fit(&mut self, rounding: SizeRounding) {
+        for atlas_id in self.atlas_ids() {
+            let (used_width, used_height) = self.used_extent(atlas_id).unwrap();
+            let (width, height) = self.atlas_dimensions[&atlas_id];
+            let new_dimensions = (
+                rounding.round(used_width).min(width),
+                rounding.round(used_height).min(height),
+            );
+            self.resize_atlas(atlas_id, new_dimensions);
+        }
+    }
+
+    /// Describe the layout of every atlas as JSON.
+    /// `extension` is that of the exporter, used to name the atlas files.
+    pub fn manifest(&self, extension: &str) -> serde_json::Value {
+        let atlases = self
+            .atlas_ids()
+            .into_iter()
+            .map(|atlas_id| {
+                let (width, height) = self.atlas_dimensions[&atlas_id];
+                let clusters = self.atlases[&atlas_id]
+                    .iter()
+                    .map(|placed| self.cluster_manifest(placed))
+                    .collect::<Vec<_>>();
+                json!({
+                    "id": atlas_id,
+                    "file": format!("{}.{}", atlas_id, extension),
+                    "width": width,
+                    "height": height,
+                    "clusters": clusters,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "atlases": atlases })
+    }
+
+    /// Write the manifest of every atlas into `writer` as JSON
+    pub fn write_manifest(&self, extension: &str, mut writer: impl Write) -> Result<()> {
+        serde_json::to_writer_pretty(&mut writer, &self.manifest(extension))?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn cluster_manifest(&self, placed: &PlacedTextureGeometry) -> serde_json::Value {
+        let cluster = &self.clusters[&placed.cluster_id];
+        let texture = &cluster.bounding_texture;
+        let polygons = cluster
+            .uv_polygons
+            .iter()
+            .filter_map(|(polygon_id, _)| self.placed_uv_polygon_map.get(polygon_id))
+            .map(|placed_uv_polygon| {
+                json!({
+                    "id": placed_uv_polygon.polygon_id,
+                    "uv": placed_uv_polygon.placed_uv_coords,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "id": placed.cluster_id,
+            "source": texture.source.id().as_str(),
+            "crop_origin": texture.crop_origin(),
+            "crop_size": (texture.crop_width, texture.crop_height),
+            "downsample_factor": texture.downsample_factor.value(),
+            "origin": placed.origin,
+            "size": (placed.width, placed.height),
+            "rotated": placed.rotated,
+            "polygons": polygons,
+        })
+    }
+
+    // Change the size of an atlas without moving its clusters
+    fn resize_atlas(&mut self, atlas_id: AtlasID, (new_width, new_height): (u32, u32)) {
+        let (width, height) = self.atlas_dimensions[&atlas_id];
+        if (width, height) == (new_width, new_height) {
+            return;
+        }
+
+        let scale_u = width as f64 / new_width as f64;
+        let scale_v = height as f64 / new_height as f64;
+        for placed in self.atlases[&atlas_id].iter() {
+            for (polygon_id, _) in self.clusters[&placed.cluster_id].uv_polygons.iter() {
+                let Some(placed_uv_polygon) = self.placed_uv_polygon_map.get_mut(polygon_id) else {
+                    continue;
+                };
+                // V is measured from the bottom edge, which moves when the height changes
+                for (u, v) in placed_uv_polygon.placed_uv_coords.iter_mut() {
+                    *u *= scale_u;
+                    *v = 1.0 - (1.0 - *v) * scale_v;
+                }
+            }
+        }
+        self.atlas_dimensions
+            .insert(atlas_id, (new_width, new_height));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::export::{CompositeOptions, PngAtlasExporter};
+    use crate::place::{GuillotineTexturePlacer, TexturePlacerConfig};
+    use crate::texture::{DownsampleFactor, PolygonMask};
+
+    // A texture covering the whole image of the given size
+    fn texture(image: &str, width: u32, height: u32) -> PolygonMappedTexture {
+        PolygonMappedTexture::new(
+            Path::new(image),
+            (width, height),
+            &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            DownsampleFactor::new(&1.0).unwrap(),
+        )
+    }
+
+    fn pack_with_order(order: PackingOrder) -> PackedAtlasProvider {
+        let mut packer = AtlasPacker::new(AtlasPackerConfig {
+            order,
+            ..Default::default()
+        });
+        packer.add_texture("small".to_string(), texture("a.png", 8, 8));
+        packer.add_texture("large".to_string(), texture("b.png", 32, 32));
+        packer.add_texture("tall".to_string(), texture("c.png", 8, 40));
+        let config = TexturePlacerConfig::new(128, 128, 0);
+        packer.pack(GuillotineTexturePlacer::new(config)).unwrap()
+    }
+
+    fn placed_cluster_order(packed: &PackedAtlasProvider) -> Vec<PolygonID> {
+        packed.atlases[&0]
+            .iter()
+            .map(|placed| packed.clusters[&placed.cluster_id].uv_polygons[0].0.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_packing_order() {
+        let packed = pack_with_order(PackingOrder::AreaDescending);
+        assert_eq!(placed_cluster_order(&packed), ["large", "tall", "small"]);
+
+        let packed = pack_with_order(PackingOrder::HeightDescending);
+        assert_eq!(placed_cluster_order(&packed), ["tall", "large", "small"]);
+
+        let packed = pack_with_order(PackingOrder::InsertionOrder);
+        assert_eq!(placed_cluster_order(&packed), ["small", "large", "tall"]);
+    }
+
+    #[test]
+    fn test_packing_is_reproducible() {
+        let first = pack_with_order(PackingOrder::AreaDescending);
+        for _ in 0..10 {
+            let packed = pack_with_order(PackingOrder::AreaDescending);
+            for polygon_id in ["small", "large", "tall"] {
+                let expected = first.get_texture_info(&polygon_id.to_string()).unwrap();
+                let actual = packed.get_texture_info(&polygon_id.to_string()).unwrap();
+                assert_eq!(expected.cluster_id, actual.cluster_id);
+                assert_eq!(expected.placed_uv_coords, actual.placed_uv_coords);
+            }
+        }
+    }
+
+    fn pack_oversized(oversize_policy: OversizePolicy) -> Result<PackedAtlasProvider> {
+        let mut packer = AtlasPacker::new(AtlasPackerConfig {
+            oversize_policy,
+            ..Default::default()
+        });
+        // Two overlapping polygons which form a 96x48 cluster
+        for (polygon_id, uv_coords) in [
+            ("left", [(0.0, 0.0), (0.5, 0.0), (0.5, 0.5)]),
+            ("right", [(0.25, 0.0), (0.75, 0.0), (0.75, 0.5)]),
+        ] {
+            let texture = PolygonMappedTexture::new(
+                Path::new("a.png"),
+                (128, 96),
+                &uv_coords,
+                DownsampleFactor::new(&1.0).unwrap(),
+            );
+            packer.add_texture(polygon_id.to_string(), texture);
+        }
+        packer.add_texture("small".to_string(), texture("b.png", 8, 8));
+
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        packer.pack(GuillotineTexturePlacer::new(config))
+    }
+
+    #[test]
+    fn test_oversize_policy() {
+        assert!(matches!(
+            pack_oversized(OversizePolicy::Error),
+            Err(AtlasPackerError::TextureTooLarge { .. })
+        ));
+
+        let packed = pack_oversized(OversizePolicy::Downsample).unwrap();
+        assert_eq!(packed.atlases.len(), 1);
+        let info = packed.get_texture_info(&"left".to_string()).unwrap();
+        let cluster = &packed.clusters[&info.cluster_id];
+        assert!(
+            cluster.bounding_texture.crop_width as f32
+                * cluster.bounding_texture.downsample_factor.value()
+                <= 64.0
+        );
+
+        let packed = pack_oversized(OversizePolicy::Split).unwrap();
+        let left = packed.get_texture_info(&"left".to_string()).unwrap();
+        let right = packed.get_texture_info(&"right".to_string()).unwrap();
+        assert_ne!(left.cluster_id, right.cluster_id);
+
+        let packed = pack_oversized(OversizePolicy::DedicatedPage).unwrap();
+        assert_eq!(packed.atlases.len(), 2);
+        let info = packed.get_texture_info(&"left".to_string()).unwrap();
+        assert_eq!(packed.atlas_dimensions[&info.atlas_id], (96, 48));
+        let info = packed.get_texture_info(&"small".to_string()).unwrap();
+        assert_eq!(packed.atlas_dimensions[&info.atlas_id], (64, 64));
+    }
+
+    #[test]
+    fn test_page_selection() {
+        let atlas_count = |page_selection: PageSelection| {
+            let mut packer = AtlasPacker::new(AtlasPackerConfig {
+                order: PackingOrder::InsertionOrder,
+                page_selection,
+                ..Default::default()
+            });
+            packer.add_texture("a".to_string(), texture("a.png", 64, 48));
+            packer.add_texture("b".to_string(), texture("b.png", 64, 48));
+            packer.add_texture("c".to_string(), texture("c.png", 64, 16));
+            packer.add_texture("d".to_string(), texture("d.png", 64, 16));
+
+            let config = TexturePlacerConfig::new(64, 64, 0);
+            let packed = packer.pack(GuillotineTexturePlacer::new(config)).unwrap();
+            packed.atlases.len()
+        };
+
+        assert_eq!(atlas_count(PageSelection::CurrentPage), 3);
+        assert_eq!(atlas_count(PageSelection::FirstFit), 2);
+        assert_eq!(atlas_count(PageSelection::BestFit), 2);
+    }
+
+    #[test]
+    fn test_shrink_to_fit() {
+        let pack = || {
+            let mut packer = AtlasPacker::default();
+            packer.add_texture("a".to_string(), texture("a.png", 40, 20));
+            packer.add_texture("b".to_string(), texture("b.png", 10, 30));
+            let config = TexturePlacerConfig::new(64, 64, 0);
+            packer.pack(GuillotineTexturePlacer::new(config)).unwrap()
+        };
+
+        let original = pack();
+        assert_eq!(original.used_extent(0), Some((40, 50)));
+
+        let mut packed = pack();
+        packed.shrink_to_fit(SizeRounding::None);
+        assert_eq!(packed.atlas_dimensions(0), Some((40, 50)));
+
+        // The placed UV coordinates still point at the same pixels
+        for polygon_id in ["a", "b"] {
+            let before = original.get_texture_info(&polygon_id.to_string()).unwrap();
+            let after = packed.get_texture_info(&polygon_id.to_string()).unwrap();
+            for (&(u0, v0), &(u1, v1)) in
+                before.placed_uv_coords.iter().zip(&after.placed_uv_coords)
+            {
+                assert!((u0 * 64.0 - u1 * 40.0).abs() < 1e-9);
+                assert!(((1.0 - v0) * 64.0 - (1.0 - v1) * 50.0).abs() < 1e-9);
+            }
+        }
+
+        let mut packed = pack();
+        packed.shrink_to_fit(SizeRounding::PowerOfTwo);
+        assert_eq!(packed.atlas_dimensions(0), Some((64, 64)));
+
+        let mut packed = pack();
+        packed.shrink_to_fit(SizeRounding::MultipleOf(4));
+        assert_eq!(packed.atlas_dimensions(0), Some((40, 52)));
+    }
+
+    #[test]
+    fn test_overlap_test() {
+        let cluster_count = |overlap_test: OverlapTest| {
+            let mut packer = AtlasPacker::new(AtlasPackerConfig {
+                overlap_test,
+                ..Default::default()
+            });
+            // Triangles in opposite corners, whose bounding boxes overlap but whose areas do not
+            let triangles = [
+                ("lower", "a.png", [(0.0, 0.0), (0.6, 0.0), (0.0, 0.6)]),
+                ("upper", "a.png", [(1.0, 1.0), (0.5, 1.0), (1.0, 0.5)]),
+                ("other", "b.png", [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)]),
+            ];
+            for (polygon_id, image, uv_coords) in triangles {
+                let texture = PolygonMappedTexture::new(
+                    Path::new(image),
+                    (10, 10),
+                    &uv_coords,
+                    DownsampleFactor::new(&1.0).unwrap(),
+                );
+                packer.add_texture(polygon_id.to_string(), texture);
+            }
+            packer.create_clusters(0, 0, (1024, 1024)).len()
+        };
+
+        assert_eq!(cluster_count(OverlapTest::BoundingBox), 2);
+        assert_eq!(cluster_count(OverlapTest::Polygon { tolerance: 0.0 }), 3);
+        // The hypotenuses are 9 / sqrt(2) = 6.36 pixels apart
+        assert_eq!(cluster_count(OverlapTest::Polygon { tolerance: 6.0 }), 3);
+        assert_eq!(cluster_count(OverlapTest::Polygon { tolerance: 6.5 }), 2);
+    }
+
+    #[test]
+    fn test_cluster_limits() {
+        let clusters = |cluster_limits: Option<ClusterLimits>, downsample_factor: f32| {
+            let mut packer = AtlasPacker::new(AtlasPackerConfig {
+                cluster_limits,
+                ..Default::default()
+            });
+            // A diagonal chain of 16x16 squares on a 128x128 image, each overlapping the next
+            for i in 0..8 {
+                let (min, max) = ((i * 12) as f64 / 128.0, (i * 12 + 16) as f64 / 128.0);
+                let texture = PolygonMappedTexture::new(
+                    Path::new("a.png"),
+                    (128, 128),
+                    &[
+                        (min, 1.0 - max),
+                        (max, 1.0 - max),
+                        (max, 1.0 - min),
+                        (min, 1.0 - min),
+                    ],
+                    DownsampleFactor::new(&downsample_factor).unwrap(),
+                );
+                packer.add_texture(i.to_string(), texture);
+            }
+            packer.create_clusters(0, 0, (64, 64))
+        };
+        let sizes = |clusters: Vec<(ClusterID, Cluster)>| {
+            clusters
+                .iter()
+                .map(|(_, cluster)| {
+                    let texture = &cluster.bounding_texture;
+                    (texture.crop_width, cluster.uv_polygons.len())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Transitively merged into a single crop that is less than half used
+        assert_eq!(sizes(clusters(None, 1.0)), [(100, 8)]);
+
+        // A third square would drop the fill ratio to 768 / 1600
+        let limits = ClusterLimits {
+            min_fill_ratio: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(sizes(clusters(Some(limits), 1.0)), [(28, 2); 4]);
+
+        // At most 16 pixels after downsampling
+        let limits = ClusterLimits {
+            max_size_ratio: 0.25,
+            ..Default::default()
+        };
+        assert_eq!(sizes(clusters(Some(limits), 1.0)), [(16, 1); 8]);
+        assert_eq!(sizes(clusters(Some(limits), 0.5)), [(28, 2); 4]);
+    }
+
+    #[test]
+    fn test_incremental_packing() {
+        let mut packer = AtlasPacker::default();
+        packer.add_texture("a".to_string(), texture("a.png", 40, 20));
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        let mut packer = packer.into_incremental(GuillotineTexturePlacer::new(config));
+        assert_eq!(packer.pending_count(), 1);
+        packer.pack_pending().unwrap();
+        let placed_a = packer.provider().atlases[&0][0].clone();
+
+        // Fits in the free space of the first atlas
+        packer.add_texture("b".to_string(), texture("b.png", 10, 30));
+        // Already packed, so ignored
+        packer.add_texture("a".to_string(), texture("c.png", 8, 8));
+        assert_eq!(packer.pending_count(), 1);
+        packer.pack_pending().unwrap();
+        assert_eq!(packer.provider().atlas_ids(), [0]);
+
+        // Needs a new atlas
+        packer.add_texture("c".to_string(), texture("c.png", 64, 64));
+        packer.pack_pending().unwrap();
+        assert_eq!(packer.pending_count(), 0);
+
+        let packed = packer.into_provider();
+        assert_eq!(packed.atlas_ids(), [0, 1]);
+        assert_eq!(packed.clusters.len(), 3);
+        let atlas_of = |id: &str| packed.get_texture_info(&id.to_string()).unwrap().atlas_id;
+        assert_eq!((atlas_of("a"), atlas_of("b"), atlas_of("c")), (0, 0, 1));
+
+        // The first placement has not moved
+        let placed = &packed.atlases[&0][0];
+        assert_eq!(placed.cluster_id, placed_a.cluster_id);
+        assert_eq!(
+            (placed.origin, placed.width, placed.height),
+            (placed_a.origin, placed_a.width, placed_a.height)
+        );
+        assert_eq!(
+            packed.clusters[&placed.cluster_id]
+                .bounding_texture
+                .source
+                .id()
+                .as_str(),
+            "a.png"
+        );
+    }
+
+    #[test]
+    fn test_failed_batch_stays_pending() {
+        let mut packer = AtlasPacker::new(AtlasPackerConfig {
+            order: PackingOrder::InsertionOrder,
+            ..Default::default()
+        });
+        packer.add_texture("a".to_string(), texture("a.png", 20, 20));
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        let mut packer = packer.into_incremental(GuillotineTexturePlacer::new(config));
+        packer.pack_pending().unwrap();
+
+        // "b" is placed on a new atlas before "c" turns out to be too large
+        packer.add_texture("b".to_string(), texture("b.png", 60, 60));
+        packer.add_texture("c".to_string(), texture("c.png", 100, 100));
+        assert!(matches!(
+            packer.pack_pending(),
+            Err(AtlasPackerError::TextureTooLarge { .. })
+        ));
+        assert_eq!(packer.pending_count(), 2);
+        let packed = packer.provider();
+        assert_eq!(packed.atlas_ids(), [0]);
+        assert_eq!(packed.clusters.len(), 1);
+        assert!(packed.get_texture_info(&"b".to_string()).is_none());
+
+        // The batch can be retried once the oversized polygon is removed
+        packer.remove_polygons(&["c".to_string()]);
+        packer.pack_pending().unwrap();
+        assert_eq!(packer.pending_count(), 0);
+        let packed = packer.into_provider();
+        assert_eq!(packed.atlas_ids(), [0, 1]);
+        assert_eq!(
+            packed.get_texture_info(&"b".to_string()).unwrap().atlas_id,
+            1
+        );
+    }
+
+    #[test]
+    fn test_resume_packing() {
+        let packer_config = || AtlasPackerConfig {
+            page_selection: PageSelection::FirstFit,
+            ..Default::default()
+        };
+        let mut packer = AtlasPacker::new(packer_config());
+        packer.add_texture("a".to_string(), texture("a.png", 40, 40));
+        packer.add_texture("b".to_string(), texture("b.png", 40, 40));
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        let placer = GuillotineTexturePlacer::new(config);
+        let packed = packer.pack(placer.clone()).unwrap();
+        let placed_before = packed.atlases[&0].clone();
+
+        let mut packer = IncrementalPacker::resume(packer_config(), placer, packed);
+        // Already packed, so ignored
+        packer.add_texture("a".to_string(), texture("c.png", 8, 8));
+        assert_eq!(packer.pending_count(), 0);
+
+        // Fits beside "a" without overlapping it, then needs a new atlas
+        packer.add_texture("c".to_string(), texture("c.png", 20, 20));
+        packer.add_texture("d".to_string(), texture("d.png", 64, 64));
+        packer.pack_pending().unwrap();
+        let packed = packer.provider();
+        assert_eq!(packed.atlas_ids(), [0, 1, 2]);
+        assert_eq!(packed.clusters.len(), 4);
+        let atlas_of = |id: &str| packed.get_texture_info(&id.to_string()).unwrap().atlas_id;
+        assert_eq!((atlas_of("c"), atlas_of("d")), (0, 2));
+        let atlas = &packed.atlases[&0];
+        assert_eq!(atlas[0].origin, placed_before[0].origin);
+        crate::place::tests::assert_no_overlaps(atlas, 64, 64);
+        let cluster_of = |id: &str| &packed.get_texture_info(&id.to_string()).unwrap().cluster_id;
+        assert_ne!(cluster_of("c"), cluster_of("a"));
+        assert_ne!(cluster_of("d"), cluster_of("b"));
+
+        packer.compact().unwrap();
+        assert_eq!(packer.provider().atlas_ids(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_remove_polygons() {
+        let mut packer = AtlasPacker::new(AtlasPackerConfig {
+            page_selection: PageSelection::FirstFit,
+            ..Default::default()
+        });
+        packer.add_texture("a".to_string(), texture("a.png", 40, 40));
+        packer.add_texture("b".to_string(), texture("b.png", 40, 40));
+        packer.add_texture("c".to_string(), texture("c.png", 20, 20));
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        let mut packer = packer.into_incremental(GuillotineTexturePlacer::new(config));
+        packer.pack_pending().unwrap();
+        let atlas_of = |packed: &PackedAtlasProvider, id: &str| {
+            packed.get_texture_info(&id.to_string()).unwrap().atlas_id
+        };
+        assert_eq!(atlas_of(packer.provider(), "b"), 1);
+        let before = packer.provider().atlases[&0].clone();
+
+        // The atlas of "b" is left empty, and nothing else moves
+        packer.remove_polygons(&["b".to_string(), "unknown".to_string()]);
+        let packed = packer.provider();
+        assert!(packed.get_texture_info(&"b".to_string()).is_none());
+        assert_eq!(packed.clusters.len(), 2);
+        assert_eq!(packed.atlas_ids(), [0, 1]);
+        assert!(packed.atlases[&1].is_empty());
+        let after = &packed.atlases[&0];
+        for (before, after) in before.iter().zip(after) {
+            assert_eq!(
+                (&before.cluster_id, before.origin),
+                (&after.cluster_id, after.origin)
+            );
+        }
+
+        // The freed space is reused
+        packer.add_texture("d".to_string(), texture("d.png", 40, 40));
+        packer.pack_pending().unwrap();
+        assert_eq!(atlas_of(packer.provider(), "d"), 1);
+        assert_eq!(packer.provider().atlases[&1][0].origin, (0, 0));
+
+        // Pending polygons can be removed too
+        packer.add_texture("e".to_string(), texture("e.png", 8, 8));
+        packer.remove_polygons(&["d".to_string(), "e".to_string()]);
+        assert_eq!(packer.pending_count(), 0);
+
+        packer.compact().unwrap();
+        let packed = packer.into_provider();
+        assert_eq!(packed.atlas_ids(), [0]);
+        assert_eq!((atlas_of(&packed, "a"), atlas_of(&packed, "c")), (0, 0));
+    }
+
+    #[test]
+    fn test_readd_removed_polygon() {
+        let mut packer = AtlasPacker::default();
+        packer.add_texture("a".to_string(), texture("a.png", 20, 20));
+        packer.add_texture("b".to_string(), texture("b.png", 20, 20));
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        let mut packer = packer.into_incremental(GuillotineTexturePlacer::new(config));
+        packer.pack_pending().unwrap();
+
+        packer.remove_polygons(&["a".to_string()]);
+        packer.add_texture("a".to_string(), texture("c.png", 10, 10));
+        assert_eq!(packer.pending_count(), 1);
+        packer.pack_pending().unwrap();
+        packer.compact().unwrap();
+        assert_eq!(packer.packer.polygon_ids, ["b", "a"]);
+
+        let packed = packer.into_provider();
+        assert_eq!(packed.clusters.len(), 2);
+        let manifest = packed.manifest("png");
+        let polygon_ids = manifest["atlases"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .flat_map(|atlas| atlas["clusters"].as_array().unwrap())
+            .flat_map(|cluster| cluster["polygons"].as_array().unwrap())
+            .map(|polygon| polygon["id"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(polygon_ids.iter().filter(|id| **id == "a").count(), 1);
+        assert_eq!(polygon_ids.len(), 2);
+    }
+
+    #[test]
+    fn test_remove_polygons_from_cluster() {
+        let dir = tempfile::tempdir().unwrap();
+        let image_path = dir.path().join("a.png");
+        image::RgbaImage::from_pixel(16, 8, image::Rgba([255, 0, 0, 255]))
+            .save(&image_path)
+            .unwrap();
+
+        // Two overlapping halves of the image, which end up in one cluster
+        let half = |min_u: f64, max_u: f64| {
+            PolygonMappedTexture::new(
+                image_path.as_path(),
+                (16, 8),
+                &[(min_u, 0.0), (max_u, 0.0), (max_u, 1.0), (min_u, 1.0)],
+                DownsampleFactor::new(&1.0).unwrap(),
+            )
+        };
+        let mut packer = AtlasPacker::default();
+        packer.add_texture("left".to_string(), half(0.0, 0.5625));
+        packer.add_texture("right".to_string(), half(0.4375, 1.0));
+        let config = TexturePlacerConfig::new(32, 32, 0);
+        let mut packed = packer.pack(GuillotineTexturePlacer::new(config)).unwrap();
+        assert_eq!(packed.clusters.len(), 1);
+
+        // The cluster is not emptied, so nothing is freed
+        assert!(packed.remove_polygons(&["right".to_string()]).is_empty());
+        let placed = packed.atlases[&0][0].clone();
+        assert_eq!((placed.width, placed.height), (16, 8));
+
+        let exporter = PngAtlasExporter {
+            composite: CompositeOptions {
+                mask: Some(PolygonMask::default()),
+                bleed: None,
+            },
+            ..Default::default()
+        };
+        let texture_cache = TextureCache::new(100_000_000);
+        let encoded = packed.encode(&exporter, &texture_cache).unwrap();
+        let atlas = image::load_from_memory(&encoded[&0]).unwrap().to_rgba8();
+        let (x, y) = placed.origin;
+        assert_eq!(atlas.get_pixel(x + 1, y + 4)[3], 255);
+        assert_eq!(atlas.get_pixel(x + 14, y + 4)[3], 0);
+    }
+
+    #[test]
+    fn test_encode_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let image_path = dir.path().join("a.png");
+        image::RgbaImage::from_pixel(16, 8, image::Rgba([255, 0, 0, 255]))
+            .save(&image_path)
+            .unwrap();
+
+        let mut packer = AtlasPacker::default();
+        packer.add_texture(
+            "a".to_string(),
+            texture(image_path.to_str().unwrap(), 16, 8),
+        );
+        let config = TexturePlacerConfig::new(32, 32, 0);
+        let packed = packer.pack(GuillotineTexturePlacer::new(config)).unwrap();
+
+        let texture_cache = TextureCache::new(100_000_000);
+        let encoded = packed
+            .encode(&PngAtlasExporter::default(), &texture_cache)
+            .unwrap();
+        assert_eq!(encoded.keys().collect::<Vec<_>>(), [&0]);
+
+        let mut written = Vec::new();
+        packed
+            .export_to_writer(
+                0,
+                &PngAtlasExporter::default(),
+                &mut written,
+                &texture_cache,
+            )
+            .unwrap();
+        assert_eq!(written, encoded[&0]);
+        assert!(matches!(
+            packed.export_to_writer(
+                1,
+                &PngAtlasExporter::default(),
+                &mut written,
+                &texture_cache
+            ),
+            Err(AtlasPackerError::AtlasNotFound(1))
+        ));
+
+        // The files are the same bytes, written by the default implementation
+        packed
+            .export(&PngAtlasExporter::default(), dir.path(), &texture_cache)
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("0.png")).unwrap(),
+            encoded[&0]
+        );
+
+        let atlas = image::load_from_memory(&encoded[&0]).unwrap().to_rgba8();
+        assert_eq!(atlas.dimensions(), (32, 32));
+        assert_eq!(atlas.get_pixel(0, 0), &image::Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let mut packer = AtlasPacker::default();
+        packer.add_texture("a".to_string(), texture("a.png", 40, 20));
+        packer.add_texture("b".to_string(), texture("b.png", 10, 30));
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        let packed = packer.pack(GuillotineTexturePlacer::new(config)).unwrap();
+
+        let mut written = Vec::new();
+        packed.write_manifest("png", &mut written).unwrap();
+        let manifest: serde_json::Value = serde_json::from_slice(&written).unwrap();
+        assert_eq!(manifest, packed.manifest("png"));
+
+        let atlas = &manifest["atlases"][0];
+        assert_eq!(atlas["file"], "0.png");
+        assert_eq!(
+            (&atlas["width"], &atlas["height"]),
+            (&json!(64), &json!(64))
+        );
+
+        let clusters = atlas["clusters"].as_array().unwrap();
+        assert_eq!(clusters.len(), 2);
+        let cluster = clusters
+            .iter()
+            .find(|cluster| cluster["source"] == "b.png")
+            .unwrap();
+        assert_eq!(cluster["crop_origin"], json!([0, 0]));
+        assert_eq!(cluster["crop_size"], json!([10, 30]));
+        assert_eq!(cluster["downsample_factor"], 1.0);
+        assert_eq!(cluster["polygons"][0]["id"], "b");
+
+        let placed = packed.get_texture_info(&"b".to_string()).unwrap();
+        assert_eq!(cluster["polygons"][0]["uv"], json!(placed.placed_uv_coords));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_roundtrip() {
+        let image = image::RgbaImage::from_pixel(16, 8, image::Rgba([0, 255, 0, 255]));
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let source = TextureSource::bytes("glb/0", png.into_inner());
+
+        let mut packer = AtlasPacker::default();
+        packer.add_texture("a".to_string(), texture("a.png", 40, 20));
+        packer.add_texture(
+            "b".to_string(),
+            PolygonMappedTexture::new(
+                source.clone(),
+                (16, 8),
+                &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+                DownsampleFactor::new(&1.0).unwrap(),
+            ),
+        );
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        let packed = packer.pack(GuillotineTexturePlacer::new(config)).unwrap();
+
+        let json = serde_json::to_string(&packed).unwrap();
+        let mut restored: PackedAtlasProvider = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.manifest("png"), packed.manifest("png"));
+
+        // The in-memory image has to be attached again before exporting
+        let texture_cache = TextureCache::new(100_000_000);
+        let cluster_id = &packed
+            .get_texture_info(&"b".to_string())
+            .unwrap()
+            .cluster_id;
+        let cluster_source = &restored.clusters[cluster_id].bounding_texture.source;
+        assert!(matches!(
+            texture_cache.get_image(cluster_source),
+            Err(AtlasPackerError::SourceDetached(_))
+        ));
+        restored.attach_source(&source);
+        let cluster_source = &restored.clusters[cluster_id].bounding_texture.source;
+        assert_eq!(
+            texture_cache.get_image(cluster_source).unwrap().to_rgba8(),
+            image
+        );
+
+        // Invalid downsample factors are rejected
+        assert!(serde_json::from_str::<DownsampleFactor>("2.0").is_err());
+        let factor: DownsampleFactor = serde_json::from_str("0.5").unwrap();
+        assert_eq!(factor.value(), 0.5);
+    }
 }
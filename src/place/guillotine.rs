@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use super::{
+    place_cluster, subtract_rect, PlacedTextureGeometry, PlacedUVPolygon, Rect, TexturePlacer,
+    TexturePlacerConfig,
+};
+use crate::{
+    error::{AtlasPackerError, Result},
+    texture::{ChildUVPolygon, ClusterBoundingTexture},
+    AtlasID, ClusterID, PolygonID,
+};
+
+#[derive(Clone)]
+pub struct GuillotineTexturePlacer {
+    config: TexturePlacerConfig,
+    free_rects: Vec<Rect>,
+    used_rects: HashMap<String, PlacedTextureGeometry>,
+}
+
+impl GuillotineTexturePlacer {
+    pub fn new(config: TexturePlacerConfig) -> Self {
+        let initial_rect = Rect {
+            x: 0,
+            y: 0,
+            width: config.width,
+            height: config.height,
+        };
+        GuillotineTexturePlacer {
+            config,
+            free_rects: vec![initial_rect],
+            used_rects: HashMap::new(),
+        }
+    }
+
+    // Returns the smallest free rectangle that fits, and whether the texture must be rotated
+    fn find_best_rect(&self, width: u32, height: u32) -> Option<(Rect, bool)> {
+        self.config
+            .orientations(width, height)
+            .filter_map(|(width, height, rotated)| {
+                self.free_rects
+                    .iter()
+                    .filter(|&rect| rect.width >= width && rect.height >= height)
+                    .min_by_key(|&rect| rect.width * rect.height)
+                    .map(|&rect| (rect, rotated))
+            })
+            .min_by_key(|(rect, _)| rect.width * rect.height)
+    }
+
+    fn split_rect(&mut self, rect: Rect, placed: &PlacedTextureGeometry) {
+        let placed_width = self.config.footprint(placed.width);
+        let placed_height = self.config.footprint(placed.height);
+        let (right_rect, bottom_rect) = if rect.width <= rect.height {
+            (
+                Rect {
+                    x: rect.x + placed_width,
+                    y: rect.y,
+                    width: rect.width - placed_width,
+                    height: placed_height,
+                },
+                Rect {
+                    x: rect.x,
+                    y: rect.y + placed_height,
+                    width: rect.width,
+                    height: rect.height - placed_height,
+                },
+            )
+        } else {
+            (
+                Rect {
+                    x: rect.x + placed_width,
+                    y: rect.y,
+                    width: rect.width - placed_width,
+                    height: rect.height,
+                },
+                Rect {
+                    x: rect.x,
+                    y: rect.y + placed_height,
+                    width: placed_width,
+                    height: rect.height - placed_height,
+                },
+            )
+        };
+
+        if right_rect.width > 0 && right_rect.height > 0 {
+            self.free_rects.push(right_rect);
+        }
+        if bottom_rect.width > 0 && bottom_rect.height > 0 {
+            self.free_rects.push(bottom_rect);
+        }
+    }
+
+    fn merge_free_rects(&mut self) {
+        let mut i = 0;
+        while i < self.free_rects.len() {
+            let mut merged = false;
+            let rect1 = self.free_rects[i];
+            let mut j = i + 1;
+            while j < self.free_rects.len() {
+                let rect2 = self.free_rects[j];
+                if let Some(merged_rect) = Self::try_merge_rects(rect1, rect2) {
+                    self.free_rects[i] = merged_rect;
+                    self.free_rects.swap_remove(j);
+                    merged = true;
+                    break;
+                }
+                j += 1;
+            }
+            if !merged {
+                i += 1;
+            }
+        }
+    }
+
+    fn try_merge_rects(rect1: Rect, rect2: Rect) -> Option<Rect> {
+        if rect1.x == rect2.x && rect1.width == rect2.width && rect1.y + rect1.height == rect2.y {
+            Some(Rect {
+                x: rect1.x,
+                y: rect1.y,
+                width: rect1.width,
+                height: rect1.height + rect2.height,
+            })
+        } else if rect1.y == rect2.y
+            && rect1.height == rect2.height
+            && rect1.x + rect1.width == rect2.x
+        {
+            Some(Rect {
+                x: rect1.x,
+                y: rect1.y,
+                width: rect1.width + rect2.width,
+                height: rect1.height,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl TexturePlacer for GuillotineTexturePlacer {
+    fn config(&self) -> &TexturePlacerConfig {
+        &self.config
+    }
+
+    fn place_texture(
+        &mut self,
+        bounding_texture: ClusterBoundingTexture,
+        children: Vec<(PolygonID, ChildUVPolygon)>,
+        cluster_id: ClusterID,
+        parent_atlas_id: AtlasID,
+    ) -> Result<(PlacedTextureGeometry, Vec<Option<PlacedUVPolygon>>)> {
+        let (scaled_width, scaled_height) = self.scale_dimensions(
+            bounding_texture.crop_width,
+            bounding_texture.crop_height,
+            bounding_texture.downsample_factor.value(),
+        );
+
+        if let Some((rect, rotated)) = self.find_best_rect(
+            self.config.footprint(scaled_width),
+            self.config.footprint(scaled_height),
+        ) {
+            let (bounding_placed, children_placed) = place_cluster(
+                &self.config,
+                rect,
+                (scaled_width, scaled_height),
+                rotated,
+                &children,
+                cluster_id.clone(),
+                parent_atlas_id,
+            );
+
+            self.used_rects.insert(cluster_id, bounding_placed.clone());
+            self.free_rects.retain(|r| r != &rect);
+            self.split_rect(rect, &bounding_placed);
+            self.merge_free_rects();
+            Ok((bounding_placed, children_placed))
+        } else {
+            Err(AtlasPackerError::PlacementFailed(cluster_id))
+        }
+    }
+
+    fn can_place(&self, texture: &ClusterBoundingTexture) -> bool {
+        let (scaled_width, scaled_height) = self.scale_dimensions(
+            texture.crop_width,
+            texture.crop_height,
+            texture.downsample_factor.value(),
+        );
+        self.config
+            .orientations(
+                self.config.footprint(scaled_width),
+                self.config.footprint(scaled_height),
+            )
+            .any(|(width, height, _)| {
+                self.free_rects
+                    .iter()
+                    .any(|r| r.width >= width && r.height >= height)
+            })
+    }
+
+    fn free_texture(&mut self, placed: &PlacedTextureGeometry) {
+        if self.used_rects.remove(&placed.cluster_id).is_some() {
+            self.free_rects.push(self.config.occupied_rect(placed));
+            self.merge_free_rects();
+        }
+    }
+
+    fn occupy_texture(&mut self, placed: &PlacedTextureGeometry) {
+        if self.used_rects.contains_key(&placed.cluster_id) {
+            return;
+        }
+        let rect = self.config.occupied_rect(placed);
+        self.free_rects = self
+            .free_rects
+            .iter()
+            .flat_map(|free_rect| subtract_rect(free_rect, &rect))
+            .collect();
+        self.merge_free_rects();
+        self.used_rects
+            .insert(placed.cluster_id.clone(), placed.clone());
+    }
+
+    fn reset_param(&mut self) {
+        let initial_rect = Rect {
+            x: 0,
+            y: 0,
+            width: self.config.width,
+            height: self.config.height,
+        };
+        self.free_rects = vec![initial_rect];
+        self.used_rects.clear();
+    }
+}
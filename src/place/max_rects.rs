@@ -0,0 +1,332 @@
+use super::{
+    place_cluster, PlacedTextureGeometry, PlacedUVPolygon, Rect, TexturePlacer, TexturePlacerConfig,
+};
+use crate::{
+    error::{AtlasPackerError, Result},
+    texture::{ChildUVPolygon, ClusterBoundingTexture},
+    AtlasID, ClusterID, PolygonID,
+};
+
+/// Rule for choosing the free rectangle in which a texture is placed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MaxRectsHeuristic {
+    /// Minimize the shorter leftover side of the free rectangle
+    #[default]
+    BestShortSideFit,
+    /// Minimize the longer leftover side of the free rectangle
+    BestLongSideFit,
+    /// Minimize the leftover area of the free rectangle
+    BestAreaFit,
+    /// Place as close to the top-left corner as possible (Tetris-like)
+    BottomLeft,
+    /// Maximize the length of edges touching the atlas border or placed textures
+    ContactPoint,
+}
+
+/// Texture placer based on the MaxRects algorithm.
+/// Free space is kept as a list of maximal (possibly overlapping) rectangles,
+/// which wastes less space than the guillotine split.
+#[derive(Clone)]
+pub struct MaxRectsTexturePlacer {
+    config: TexturePlacerConfig,
+    heuristic: MaxRectsHeuristic,
+    free_rects: Vec<Rect>,
+    used_rects: Vec<Rect>,
+}
+
+impl MaxRectsTexturePlacer {
+    pub fn new(config: TexturePlacerConfig, heuristic: MaxRectsHeuristic) -> Self {
+        let initial_rect = Rect {
+            x: 0,
+            y: 0,
+            width: config.width,
+            height: config.height,
+        };
+        MaxRectsTexturePlacer {
+            config,
+            heuristic,
+            free_rects: vec![initial_rect],
+            used_rects: Vec::new(),
+        }
+    }
+
+    pub fn heuristic(&self) -> MaxRectsHeuristic {
+        self.heuristic
+    }
+
+    // Returns the best position for the texture, and whether it must be rotated
+    fn find_best_rect(&self, width: u32, height: u32) -> Option<(Rect, bool)> {
+        self.config
+            .orientations(width, height)
+            .flat_map(|(width, height, rotated)| {
+                self.free_rects
+                    .iter()
+                    .filter(move |&rect| rect.width >= width && rect.height >= height)
+                    .map(move |free_rect| {
+                        let candidate = Rect {
+                            x: free_rect.x,
+                            y: free_rect.y,
+                            width,
+                            height,
+                        };
+                        (self.score(free_rect, &candidate), candidate, rotated)
+                    })
+            })
+            .min_by_key(|(score, _, _)| *score)
+            .map(|(_, candidate, rotated)| (candidate, rotated))
+    }
+
+    // Lower is better
+    fn score(&self, free_rect: &Rect, candidate: &Rect) -> (i64, i64) {
+        let leftover_horizontal = (free_rect.width - candidate.width) as i64;
+        let leftover_vertical = (free_rect.height - candidate.height) as i64;
+        let short_side = leftover_horizontal.min(leftover_vertical);
+        let long_side = leftover_horizontal.max(leftover_vertical);
+
+        match self.heuristic {
+            MaxRectsHeuristic::BestShortSideFit => (short_side, long_side),
+            MaxRectsHeuristic::BestLongSideFit => (long_side, short_side),
+            MaxRectsHeuristic::BestAreaFit => {
+                let leftover_area = free_rect.width as i64 * free_rect.height as i64
+                    - candidate.width as i64 * candidate.height as i64;
+                (leftover_area, short_side)
+            }
+            MaxRectsHeuristic::BottomLeft => (candidate.bottom() as i64, candidate.x as i64),
+            MaxRectsHeuristic::ContactPoint => (-(self.contact_score(candidate) as i64), 0),
+        }
+    }
+
+    fn contact_score(&self, candidate: &Rect) -> u64 {
+        let mut score = 0;
+        if candidate.x == 0 || candidate.right() == self.config.width {
+            score += candidate.height as u64;
+        }
+        if candidate.y == 0 || candidate.bottom() == self.config.height {
+            score += candidate.width as u64;
+        }
+        for used in &self.used_rects {
+            if used.x == candidate.right() || used.right() == candidate.x {
+                score += common_interval(used.y, used.bottom(), candidate.y, candidate.bottom());
+            }
+            if used.y == candidate.bottom() || used.bottom() == candidate.y {
+                score += common_interval(used.x, used.right(), candidate.x, candidate.right());
+            }
+        }
+        score
+    }
+
+    fn place_rect(&mut self, used: Rect) {
+        let mut split_rects = Vec::new();
+        self.free_rects.retain(|free_rect| {
+            if free_rect.intersects(&used) {
+                split_rects.extend(split_free_rect(free_rect, &used));
+                false
+            } else {
+                true
+            }
+        });
+        self.free_rects.extend(split_rects);
+        self.prune_free_rects();
+        self.used_rects.push(used);
+    }
+
+    // Recompute the maximal free rectangles around the remaining used rectangles
+    fn rebuild_free_rects(&mut self) {
+        let used_rects = std::mem::take(&mut self.used_rects);
+        self.free_rects = vec![Rect {
+            x: 0,
+            y: 0,
+            width: self.config.width,
+            height: self.config.height,
+        }];
+        for used in used_rects {
+            self.place_rect(used);
+        }
+    }
+
+    // Remove free rectangles that are contained in another one
+    fn prune_free_rects(&mut self) {
+        let mut i = 0;
+        while i < self.free_rects.len() {
+            let mut removed = false;
+            let mut j = i + 1;
+            while j < self.free_rects.len() {
+                if self.free_rects[j].contains(&self.free_rects[i]) {
+                    self.free_rects.swap_remove(i);
+                    removed = true;
+                    break;
+                }
+                if self.free_rects[i].contains(&self.free_rects[j]) {
+                    self.free_rects.swap_remove(j);
+                } else {
+                    j += 1;
+                }
+            }
+            if !removed {
+                i += 1;
+            }
+        }
+    }
+}
+
+fn common_interval(start_0: u32, end_0: u32, start_1: u32, end_1: u32) -> u64 {
+    if end_0 < start_1 || end_1 < start_0 {
+        0
+    } else {
+        (end_0.min(end_1) - start_0.max(start_1)) as u64
+    }
+}
+
+// Split a free rectangle into the maximal rectangles that do not overlap `used`
+fn split_free_rect(free_rect: &Rect, used: &Rect) -> Vec<Rect> {
+    let mut rects = Vec::with_capacity(4);
+
+    if used.x < free_rect.right() && used.right() > free_rect.x {
+        if used.y > free_rect.y && used.y < free_rect.bottom() {
+            rects.push(Rect {
+                height: used.y - free_rect.y,
+                ..*free_rect
+            });
+        }
+        if used.bottom() < free_rect.bottom() {
+            rects.push(Rect {
+                y: used.bottom(),
+                height: free_rect.bottom() - used.bottom(),
+                ..*free_rect
+            });
+        }
+    }
+
+    if used.y < free_rect.bottom() && used.bottom() > free_rect.y {
+        if used.x > free_rect.x && used.x < free_rect.right() {
+            rects.push(Rect {
+                width: used.x - free_rect.x,
+                ..*free_rect
+            });
+        }
+        if used.right() < free_rect.right() {
+            rects.push(Rect {
+                x: used.right(),
+                width: free_rect.right() - used.right(),
+                ..*free_rect
+            });
+        }
+    }
+
+    rects
+}
+
+impl TexturePlacer for MaxRectsTexturePlacer {
+    fn config(&self) -> &TexturePlacerConfig {
+        &self.config
+    }
+
+    fn place_texture(
+        &mut self,
+        bounding_texture: ClusterBoundingTexture,
+        children: Vec<(PolygonID, ChildUVPolygon)>,
+        cluster_id: ClusterID,
+        parent_atlas_id: AtlasID,
+    ) -> Result<(PlacedTextureGeometry, Vec<Option<PlacedUVPolygon>>)> {
+        let (scaled_width, scaled_height) = self.scale_dimensions(
+            bounding_texture.crop_width,
+            bounding_texture.crop_height,
+            bounding_texture.downsample_factor.value(),
+        );
+
+        if let Some((rect, rotated)) = self.find_best_rect(
+            self.config.footprint(scaled_width),
+            self.config.footprint(scaled_height),
+        ) {
+            self.place_rect(rect);
+            Ok(place_cluster(
+                &self.config,
+                rect,
+                (scaled_width, scaled_height),
+                rotated,
+                &children,
+                cluster_id,
+                parent_atlas_id,
+            ))
+        } else {
+            Err(AtlasPackerError::PlacementFailed(cluster_id))
+        }
+    }
+
+    fn can_place(&self, texture: &ClusterBoundingTexture) -> bool {
+        let (scaled_width, scaled_height) = self.scale_dimensions(
+            texture.crop_width,
+            texture.crop_height,
+            texture.downsample_factor.value(),
+        );
+        self.config
+            .orientations(
+                self.config.footprint(scaled_width),
+                self.config.footprint(scaled_height),
+            )
+            .any(|(width, height, _)| {
+                self.free_rects
+                    .iter()
+                    .any(|r| r.width >= width && r.height >= height)
+            })
+    }
+
+    fn free_texture(&mut self, placed: &PlacedTextureGeometry) {
+        let rect = self.config.occupied_rect(placed);
+        if let Some(index) = self.used_rects.iter().position(|used| *used == rect) {
+            self.used_rects.swap_remove(index);
+            self.rebuild_free_rects();
+        }
+    }
+
+    fn occupy_texture(&mut self, placed: &PlacedTextureGeometry) {
+        let rect = self.config.occupied_rect(placed);
+        if !self.used_rects.contains(&rect) {
+            self.place_rect(rect);
+        }
+    }
+
+    fn reset_param(&mut self) {
+        let initial_rect = Rect {
+            x: 0,
+            y: 0,
+            width: self.config.width,
+            height: self.config.height,
+        };
+        self.free_rects = vec![initial_rect];
+        self.used_rects.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::place::tests::{assert_no_overlaps, bounding_texture};
+
+    #[test]
+    fn test_max_rects_fills_atlas() {
+        for heuristic in [
+            MaxRectsHeuristic::BestShortSideFit,
+            MaxRectsHeuristic::BestLongSideFit,
+            MaxRectsHeuristic::BestAreaFit,
+            MaxRectsHeuristic::BottomLeft,
+            MaxRectsHeuristic::ContactPoint,
+        ] {
+            let config = TexturePlacerConfig::new(64, 64, 0);
+            let mut placer = MaxRectsTexturePlacer::new(config, heuristic);
+            let sizes = [(32, 48), (32, 48), (32, 16), (32, 16)];
+
+            let mut placed: Vec<PlacedTextureGeometry> = Vec::new();
+            for (i, (width, height)) in sizes.into_iter().enumerate() {
+                let texture = bounding_texture(width, height);
+                assert!(placer.can_place(&texture), "{:?}", heuristic);
+                let (geometry, _) = placer
+                    .place_texture(texture, vec![], i.to_string(), 0)
+                    .unwrap();
+                placed.push(geometry);
+            }
+            assert!(!placer.can_place(&bounding_texture(1, 1)));
+            assert_no_overlaps(&placed, 64, 64);
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use crate::{
+    error::Result,
+    texture::{ChildUVPolygon, ClusterBoundingTexture},
+    AtlasID, ClusterID, PolygonID,
+};
+
+mod guillotine;
+mod max_rects;
+mod skyline;
+
+pub use guillotine::GuillotineTexturePlacer;
+pub use max_rects::{MaxRectsHeuristic, MaxRectsTexturePlacer};
+pub use skyline::SkylineTexturePlacer;
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TexturePlacerConfig {
+    pub width: u32,
+    pub height: u32,
+    pub padding: u32,
+    // Allow textures to be rotated by 90 degrees when it helps them fit
+    pub allow_rotation: bool,
+    // Align the origins of the textures and the space they occupy to multiples of this,
+    // e.g. 4 so that block-compressed (BCn) atlases never share a block between textures
+    pub block_alignment: u32,
+    // and more option
+    // Adjust resolution, specify resampling method, etc...
+}
+
+impl Default for TexturePlacerConfig {
+    fn default() -> Self {
+        TexturePlacerConfig {
+            width: 1024,
+            height: 1024,
+            padding: 0,
+            allow_rotation: false,
+            block_alignment: 1,
+        }
+    }
+}
+
+impl TexturePlacerConfig {
+    // Ensure that the width and height are powers of two
+    pub fn new(width: u32, height: u32, padding: u32) -> Self {
+        TexturePlacerConfig {
+            width: width.checked_next_power_of_two().unwrap(),
+            height: height.checked_next_power_of_two().unwrap(),
+            padding,
+            allow_rotation: false,
+            block_alignment: 1,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn padding(&self) -> u32 {
+        self.padding
+    }
+
+    pub fn allow_rotation(&self) -> bool {
+        self.allow_rotation
+    }
+
+    pub fn block_alignment(&self) -> u32 {
+        self.block_alignment
+    }
+
+    // Round the length up to the block alignment
+    pub(crate) fn align(&self, length: u32) -> u32 {
+        length.next_multiple_of(self.block_alignment.max(1))
+    }
+
+    // Offset of a texture from the top-left corner of the rectangle it occupies
+    pub(crate) fn padding_offset(&self) -> u32 {
+        self.align(self.padding)
+    }
+
+    // Length that a texture occupies on the atlas, including its padding
+    pub(crate) fn footprint(&self, length: u32) -> u32 {
+        self.padding_offset() + self.align(length)
+    }
+
+    // Rectangle occupied by a placed texture, including its padding
+    fn occupied_rect(&self, placed: &PlacedTextureGeometry) -> Rect {
+        Rect {
+            x: placed.origin.0 - self.padding_offset(),
+            y: placed.origin.1 - self.padding_offset(),
+            width: self.footprint(placed.width),
+            height: self.footprint(placed.height),
+        }
+    }
+
+    // Candidate (width, height, rotated) orientations of a texture
+    fn orientations(&self, width: u32, height: u32) -> impl Iterator<Item = (u32, u32, bool)> {
+        let rotated = (self.allow_rotation && width != height).then_some((height, width, true));
+        std::iter::once((width, height, false)).chain(rotated)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlacedTextureGeometry {
+    pub cluster_id: ClusterID,
+    pub atlas_id: AtlasID,
+    // Pixel coordinates on atlas
+    pub origin: (u32, u32),
+    // Size on atlas (after rotation)
+    pub width: u32,
+    pub height: u32,
+    // The cropped texture is rotated 90 degrees clockwise on atlas
+    pub rotated: bool,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlacedUVPolygon {
+    pub polygon_id: PolygonID,
+    pub cluster_id: ClusterID,
+    pub atlas_id: AtlasID,
+    // UV coordinates on atlas (rotation already applied)
+    pub placed_uv_coords: Vec<(f64, f64)>,
+}
+
+pub trait TexturePlacer: Send + Sync {
+    fn config(&self) -> &TexturePlacerConfig;
+
+    fn place_texture(
+        &mut self,
+        bounding_texture: ClusterBoundingTexture,
+        children: Vec<(PolygonID, ChildUVPolygon)>,
+        cluster_id: ClusterID,
+        parent_atlas_id: AtlasID,
+    ) -> Result<(PlacedTextureGeometry, Vec<Option<PlacedUVPolygon>>)>;
+
+    fn can_place(&self, texture: &ClusterBoundingTexture) -> bool;
+
+    /// Return the space occupied by a texture placed by this placer, so that it can be reused
+    fn free_texture(&mut self, placed: &PlacedTextureGeometry);
+
+    /// Mark the space of a texture placed earlier as occupied, e.g. to rebuild the placer of an existing atlas.
+    /// The placement must not overlap the textures already placed by this placer.
+    fn occupy_texture(&mut self, placed: &PlacedTextureGeometry);
+
+    fn reset_param(&mut self);
+
+    fn scale_dimensions(&self, width: u32, height: u32, downsample_factor: f32) -> (u32, u32) {
+        let scaled_width = (width as f32 * downsample_factor).max(1.0) as u32;
+        let scaled_height = (height as f32 * downsample_factor).max(1.0) as u32;
+        (scaled_width, scaled_height)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Rect {
+    fn right(&self) -> u32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+
+    fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.right() <= self.right()
+            && other.bottom() <= self.bottom()
+    }
+
+    fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+}
+
+// Split a free rectangle into disjoint rectangles that do not overlap `used`:
+// the full-width strips above and below it, and the sides in between
+fn subtract_rect(free_rect: &Rect, used: &Rect) -> Vec<Rect> {
+    if !free_rect.intersects(used) {
+        return vec![*free_rect];
+    }
+
+    let top = used.y.max(free_rect.y);
+    let bottom = used.bottom().min(free_rect.bottom());
+    let rects = [
+        Rect {
+            height: top - free_rect.y,
+            ..*free_rect
+        },
+        Rect {
+            y: bottom,
+            height: free_rect.bottom() - bottom,
+            ..*free_rect
+        },
+        Rect {
+            y: top,
+            width: used.x.saturating_sub(free_rect.x),
+            height: bottom - top,
+            ..*free_rect
+        },
+        Rect {
+            x: used.right().max(free_rect.x),
+            y: top,
+            width: free_rect.right().saturating_sub(used.right()),
+            height: bottom - top,
+        },
+    ];
+    rects
+        .into_iter()
+        .filter(|rect| rect.width > 0 && rect.height > 0)
+        .collect()
+}
+
+/// Creates the placement of a cluster whose (padded) rectangle starts at `rect`,
+/// and maps the UV coordinates of its children onto the atlas.
+/// `size` is the scaled size of the cluster before rotation.
+fn place_cluster(
+    config: &TexturePlacerConfig,
+    rect: Rect,
+    size: (u32, u32),
+    rotated: bool,
+    children: &[(PolygonID, ChildUVPolygon)],
+    cluster_id: ClusterID,
+    parent_atlas_id: AtlasID,
+) -> (PlacedTextureGeometry, Vec<Option<PlacedUVPolygon>>) {
+    let (width, height) = if rotated { (size.1, size.0) } else { size };
+    let bounding_placed = PlacedTextureGeometry {
+        cluster_id: cluster_id.clone(),
+        atlas_id: parent_atlas_id,
+        origin: (
+            rect.x + config.padding_offset(),
+            rect.y + config.padding_offset(),
+        ),
+        width,
+        height,
+        rotated,
+    };
+
+    let children_placed = children
+        .iter()
+        .map(|(polygon_id, uv_polygon)| {
+            let placed_uv_coords = uv_polygon
+                .cropped_uv_coords
+                .iter()
+                .map(|&(u, v)| {
+                    // Rotating the image 90 degrees clockwise maps (u, v) to (v, 1 - u)
+                    let uv = if rotated { (v, 1.0 - u) } else { (u, v) };
+                    cropped_uv_to_placed_uv(config, rect, uv, width, height)
+                })
+                .collect::<Vec<(f64, f64)>>();
+            Some(PlacedUVPolygon {
+                polygon_id: polygon_id.clone(),
+                cluster_id: cluster_id.clone(),
+                atlas_id: parent_atlas_id,
+                placed_uv_coords,
+            })
+        })
+        .collect::<Vec<Option<PlacedUVPolygon>>>();
+
+    (bounding_placed, children_placed)
+}
+
+fn cropped_uv_to_placed_uv(
+    config: &TexturePlacerConfig,
+    rect: Rect,
+    uv: (f64, f64),
+    width: u32,
+    height: u32,
+) -> (f64, f64) {
+    let (x, y) = uv_to_pixel(uv, width, height);
+    (
+        (rect.x as f64 + config.padding_offset() as f64 + x) / config.width as f64,
+        1.0 - ((rect.y as f64 + config.padding_offset() as f64 + y) / config.height as f64),
+    )
+}
+
+fn uv_to_pixel(uv: (f64, f64), width: u32, height: u32) -> (f64, f64) {
+    let x = uv.0 * width as f64;
+    let y = (1.0 - uv.1) * height as f64;
+    (x, y)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::texture::{DownsampleFactor, PolygonMappedTexture};
+
+    pub fn bounding_texture(width: u32, height: u32) -> ClusterBoundingTexture {
+        let texture = PolygonMappedTexture::new(
+            Path::new("dummy.png"),
+            (width, height),
+            &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            DownsampleFactor::new(&1.0).unwrap(),
+        );
+        ClusterBoundingTexture::new(&texture)
+    }
+
+    pub fn assert_no_overlaps(placed: &[PlacedTextureGeometry], width: u32, height: u32) {
+        for (i, a) in placed.iter().enumerate() {
+            assert!(a.origin.0 + a.width <= width && a.origin.1 + a.height <= height);
+            for b in placed.iter().skip(i + 1) {
+                let overlaps = a.origin.0 < b.origin.0 + b.width
+                    && b.origin.0 < a.origin.0 + a.width
+                    && a.origin.1 < b.origin.1 + b.height
+                    && b.origin.1 < a.origin.1 + a.height;
+                assert!(!overlaps, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    fn assert_occupied_placement<P: TexturePlacer + Clone>(mut placer: P) {
+        let mut fresh = placer.clone();
+        let mut placed = [(20, 30), (30, 20), (10, 10), (24, 8)]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (width, height))| {
+                placer
+                    .place_texture(bounding_texture(width, height), vec![], i.to_string(), 0)
+                    .unwrap()
+                    .0
+            })
+            .collect::<Vec<_>>();
+
+        // Occupying the same placements, twice over, leaves only the rest of the atlas free
+        for geometry in placed.iter().chain(placed.iter()) {
+            fresh.occupy_texture(geometry);
+        }
+        let mut i = placed.len();
+        while fresh.can_place(&bounding_texture(8, 8)) {
+            let (geometry, _) = fresh
+                .place_texture(bounding_texture(8, 8), vec![], i.to_string(), 0)
+                .unwrap();
+            placed.push(geometry);
+            i += 1;
+        }
+        assert_no_overlaps(&placed, 64, 64);
+    }
+
+    #[test]
+    fn test_occupy_texture() {
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        assert_occupied_placement(GuillotineTexturePlacer::new(config.clone()));
+        assert_occupied_placement(MaxRectsTexturePlacer::new(
+            config.clone(),
+            MaxRectsHeuristic::default(),
+        ));
+        assert_occupied_placement(SkylineTexturePlacer::new(config.clone(), true));
+        assert_occupied_placement(SkylineTexturePlacer::new(config, false));
+    }
+
+    fn assert_rotated_placement<P: TexturePlacer>(mut placer: P) {
+        let texture = bounding_texture(16, 64);
+        assert!(placer.can_place(&texture));
+
+        let children = vec![(
+            "polygon".to_string(),
+            ChildUVPolygon {
+                cropped_uv_coords: vec![(0.0, 0.0), (0.0, 1.0), (1.0, 1.0)],
+            },
+        )];
+        let (geometry, placed_uvs) = placer
+            .place_texture(texture, children, "0".to_string(), 0)
+            .unwrap();
+        assert!(geometry.rotated);
+        assert_eq!((geometry.width, geometry.height), (64, 16));
+
+        let placed_uv_coords = &placed_uvs[0].as_ref().unwrap().placed_uv_coords;
+        assert_eq!(placed_uv_coords, &[(0.0, 1.0), (1.0, 1.0), (1.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let config = TexturePlacerConfig {
+            width: 64,
+            height: 16,
+            allow_rotation: true,
+            ..Default::default()
+        };
+        assert_rotated_placement(GuillotineTexturePlacer::new(config.clone()));
+        assert_rotated_placement(MaxRectsTexturePlacer::new(
+            config.clone(),
+            MaxRectsHeuristic::default(),
+        ));
+        assert_rotated_placement(SkylineTexturePlacer::new(config.clone(), true));
+
+        let config = TexturePlacerConfig {
+            allow_rotation: false,
+            ..config
+        };
+        assert!(!GuillotineTexturePlacer::new(config).can_place(&bounding_texture(16, 64)));
+    }
+
+    fn assert_block_aligned_placement<P: TexturePlacer>(mut placer: P) {
+        let alignment = placer.config().block_alignment;
+        let placed = [(5, 3), (7, 9), (10, 2), (3, 3), (13, 6)]
+            .iter()
+            .enumerate()
+            .map(|(i, &(width, height))| {
+                placer
+                    .place_texture(bounding_texture(width, height), vec![], i.to_string(), 0)
+                    .unwrap()
+                    .0
+            })
+            .collect::<Vec<_>>();
+
+        // Neither the origins nor the blocks covered by the textures are shared
+        let blocks = placed
+            .iter()
+            .map(|placed| {
+                assert_eq!(placed.origin.0 % alignment, 0);
+                assert_eq!(placed.origin.1 % alignment, 0);
+                PlacedTextureGeometry {
+                    width: placed.width.next_multiple_of(alignment),
+                    height: placed.height.next_multiple_of(alignment),
+                    ..placed.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_no_overlaps(&blocks, 64, 64);
+    }
+
+    fn assert_freed_space_is_reused<P: TexturePlacer>(mut placer: P) {
+        let mut placed = (0..4)
+            .map(|i| {
+                placer
+                    .place_texture(bounding_texture(31, 31), vec![], i.to_string(), 0)
+                    .unwrap()
+                    .0
+            })
+            .collect::<Vec<_>>();
+        assert!(!placer.can_place(&bounding_texture(31, 31)));
+
+        let freed = placed.swap_remove(1);
+        placer.free_texture(&freed);
+        assert!(placer.can_place(&bounding_texture(31, 31)));
+        let (reused, _) = placer
+            .place_texture(bounding_texture(31, 31), vec![], "4".to_string(), 0)
+            .unwrap();
+        assert_eq!(reused.origin, freed.origin);
+        placed.push(reused);
+        assert_no_overlaps(&placed, 64, 64);
+        assert!(!placer.can_place(&bounding_texture(1, 1)));
+    }
+
+    #[test]
+    fn test_free_texture() {
+        let config = TexturePlacerConfig::new(64, 64, 1);
+        assert_freed_space_is_reused(GuillotineTexturePlacer::new(config.clone()));
+        assert_freed_space_is_reused(MaxRectsTexturePlacer::new(
+            config.clone(),
+            MaxRectsHeuristic::default(),
+        ));
+        assert_freed_space_is_reused(SkylineTexturePlacer::new(config.clone(), false));
+        assert_freed_space_is_reused(SkylineTexturePlacer::new(config, true));
+    }
+
+    #[test]
+    fn test_block_alignment() {
+        let config = TexturePlacerConfig {
+            width: 64,
+            height: 64,
+            padding: 1,
+            block_alignment: 4,
+            ..Default::default()
+        };
+        assert_block_aligned_placement(GuillotineTexturePlacer::new(config.clone()));
+        assert_block_aligned_placement(MaxRectsTexturePlacer::new(
+            config.clone(),
+            MaxRectsHeuristic::default(),
+        ));
+        assert_block_aligned_placement(SkylineTexturePlacer::new(config, true));
+    }
+}
@@ -0,0 +1,439 @@
+use super::{
+    place_cluster, subtract_rect, PlacedTextureGeometry, PlacedUVPolygon, Rect, TexturePlacer,
+    TexturePlacerConfig,
+};
+use crate::{
+    error::{AtlasPackerError, Result},
+    texture::{ChildUVPolygon, ClusterBoundingTexture},
+    AtlasID, ClusterID, PolygonID,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SkylineNode {
+    x: u32,
+    // The lowest free row below the already placed textures
+    y: u32,
+    width: u32,
+}
+
+impl SkylineNode {
+    fn right(&self) -> u32 {
+        self.x + self.width
+    }
+}
+
+/// Texture placer based on the skyline bottom-left algorithm.
+/// Only the upper envelope of the placed textures is tracked, which keeps placement cheap
+/// even for many small textures. When the waste map is enabled, gaps left under the skyline
+/// are kept as free rectangles and reused by later textures.
+/// The space of freed textures is always kept as free rectangles, since the skyline cannot represent it.
+#[derive(Clone)]
+pub struct SkylineTexturePlacer {
+    config: TexturePlacerConfig,
+    use_waste_map: bool,
+    skyline: Vec<SkylineNode>,
+    waste_rects: Vec<Rect>,
+    // Rectangles of the placed textures, so that only these are ever freed
+    used_rects: Vec<Rect>,
+}
+
+impl SkylineTexturePlacer {
+    pub fn new(config: TexturePlacerConfig, use_waste_map: bool) -> Self {
+        let initial_node = SkylineNode {
+            x: 0,
+            y: 0,
+            width: config.width,
+        };
+        SkylineTexturePlacer {
+            config,
+            use_waste_map,
+            skyline: vec![initial_node],
+            waste_rects: Vec::new(),
+            used_rects: Vec::new(),
+        }
+    }
+
+    // Returns the top of the rectangle if it can be placed at the left edge of the node
+    fn fits_at(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.skyline[index].x;
+        if x + width > self.config.width {
+            return None;
+        }
+
+        let mut y = 0;
+        for node in self.skyline[index..]
+            .iter()
+            .take_while(|node| node.x < x + width)
+        {
+            y = y.max(node.y);
+            if y + height > self.config.height {
+                return None;
+            }
+        }
+        Some(y)
+    }
+
+    // Returns the node index and position with the lowest bottom edge,
+    // and whether the texture must be rotated
+    fn find_skyline_position(&self, width: u32, height: u32) -> Option<(usize, Rect, bool)> {
+        self.config
+            .orientations(width, height)
+            .flat_map(|(width, height, rotated)| {
+                (0..self.skyline.len()).filter_map(move |index| {
+                    let y = self.fits_at(index, width, height)?;
+                    let rect = Rect {
+                        x: self.skyline[index].x,
+                        y,
+                        width,
+                        height,
+                    };
+                    Some((
+                        (rect.bottom(), self.skyline[index].width),
+                        index,
+                        rect,
+                        rotated,
+                    ))
+                })
+            })
+            .min_by_key(|(score, _, _, _)| *score)
+            .map(|(_, index, rect, rotated)| (index, rect, rotated))
+    }
+
+    // Returns the index of the smallest waste rectangle that fits,
+    // and whether the texture must be rotated
+    fn find_waste_rect(&self, width: u32, height: u32) -> Option<(usize, bool)> {
+        self.config
+            .orientations(width, height)
+            .flat_map(|(width, height, rotated)| {
+                self.waste_rects
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, rect)| rect.width >= width && rect.height >= height)
+                    .map(move |(index, rect)| (rect.width * rect.height, index, rotated))
+            })
+            .min_by_key(|(area, _, _)| *area)
+            .map(|(_, index, rotated)| (index, rotated))
+    }
+
+    fn place_in_waste_rect(&mut self, index: usize, width: u32, height: u32) -> Rect {
+        let free_rect = self.waste_rects.swap_remove(index);
+        let (right_rect, bottom_rect) = if free_rect.width <= free_rect.height {
+            (
+                Rect {
+                    x: free_rect.x + width,
+                    y: free_rect.y,
+                    width: free_rect.width - width,
+                    height,
+                },
+                Rect {
+                    x: free_rect.x,
+                    y: free_rect.y + height,
+                    width: free_rect.width,
+                    height: free_rect.height - height,
+                },
+            )
+        } else {
+            (
+                Rect {
+                    x: free_rect.x + width,
+                    y: free_rect.y,
+                    width: free_rect.width - width,
+                    height: free_rect.height,
+                },
+                Rect {
+                    x: free_rect.x,
+                    y: free_rect.y + height,
+                    width,
+                    height: free_rect.height - height,
+                },
+            )
+        };
+
+        for rect in [right_rect, bottom_rect] {
+            if rect.width > 0 && rect.height > 0 {
+                self.waste_rects.push(rect);
+            }
+        }
+
+        Rect {
+            width,
+            height,
+            ..free_rect
+        }
+    }
+
+    fn add_skyline_level(&mut self, index: usize, rect: Rect) {
+        if self.use_waste_map {
+            self.add_waste_map_area(index, rect);
+        }
+
+        self.skyline.insert(
+            index,
+            SkylineNode {
+                x: rect.x,
+                y: rect.bottom(),
+                width: rect.width,
+            },
+        );
+
+        // Shrink or remove the nodes covered by the new level
+        let i = index + 1;
+        while i < self.skyline.len() {
+            let previous_right = self.skyline[i - 1].right();
+            let node = &mut self.skyline[i];
+            if node.x >= previous_right {
+                break;
+            }
+            let shrink = previous_right - node.x;
+            if node.width <= shrink {
+                self.skyline.remove(i);
+            } else {
+                node.x += shrink;
+                node.width -= shrink;
+                break;
+            }
+        }
+
+        self.merge_skylines();
+    }
+
+    fn add_waste_map_area(&mut self, index: usize, rect: Rect) {
+        for node in self.skyline[index..]
+            .iter()
+            .take_while(|node| node.x < rect.right())
+        {
+            if node.y < rect.y {
+                let left = node.x.max(rect.x);
+                let right = node.right().min(rect.right());
+                self.waste_rects.push(Rect {
+                    x: left,
+                    y: node.y,
+                    width: right - left,
+                    height: rect.y - node.y,
+                });
+            }
+        }
+    }
+
+    // Raise the skyline over a rectangle that was not placed on it, e.g. when rebuilding an atlas.
+    // Nodes that are already above the rectangle are left as they are.
+    fn raise_skyline(&mut self, rect: Rect) {
+        let mut skyline = Vec::with_capacity(self.skyline.len() + 2);
+        for node in std::mem::take(&mut self.skyline) {
+            let left = node.x.max(rect.x);
+            let right = node.right().min(rect.right());
+            if left >= right || node.y >= rect.bottom() {
+                skyline.push(node);
+                continue;
+            }
+
+            if node.x < left {
+                skyline.push(SkylineNode {
+                    width: left - node.x,
+                    ..node
+                });
+            }
+            if self.use_waste_map && node.y < rect.y {
+                self.waste_rects.push(Rect {
+                    x: left,
+                    y: node.y,
+                    width: right - left,
+                    height: rect.y - node.y,
+                });
+            }
+            skyline.push(SkylineNode {
+                x: left,
+                y: rect.bottom(),
+                width: right - left,
+            });
+            if right < node.right() {
+                skyline.push(SkylineNode {
+                    x: right,
+                    width: node.right() - right,
+                    ..node
+                });
+            }
+        }
+        self.skyline = skyline;
+        self.merge_skylines();
+    }
+
+    fn merge_skylines(&mut self) {
+        let mut i = 0;
+        while i + 1 < self.skyline.len() {
+            if self.skyline[i].y == self.skyline[i + 1].y {
+                self.skyline[i].width += self.skyline[i + 1].width;
+                self.skyline.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl TexturePlacer for SkylineTexturePlacer {
+    fn config(&self) -> &TexturePlacerConfig {
+        &self.config
+    }
+
+    fn place_texture(
+        &mut self,
+        bounding_texture: ClusterBoundingTexture,
+        children: Vec<(PolygonID, ChildUVPolygon)>,
+        cluster_id: ClusterID,
+        parent_atlas_id: AtlasID,
+    ) -> Result<(PlacedTextureGeometry, Vec<Option<PlacedUVPolygon>>)> {
+        let (scaled_width, scaled_height) = self.scale_dimensions(
+            bounding_texture.crop_width,
+            bounding_texture.crop_height,
+            bounding_texture.downsample_factor.value(),
+        );
+        let width = self.config.footprint(scaled_width);
+        let height = self.config.footprint(scaled_height);
+
+        let (rect, rotated) = if let Some((index, rotated)) = self.find_waste_rect(width, height) {
+            let (width, height) = if rotated {
+                (height, width)
+            } else {
+                (width, height)
+            };
+            (self.place_in_waste_rect(index, width, height), rotated)
+        } else if let Some((index, rect, rotated)) = self.find_skyline_position(width, height) {
+            self.add_skyline_level(index, rect);
+            (rect, rotated)
+        } else {
+            return Err(AtlasPackerError::PlacementFailed(cluster_id));
+        };
+        self.used_rects.push(rect);
+
+        Ok(place_cluster(
+            &self.config,
+            rect,
+            (scaled_width, scaled_height),
+            rotated,
+            &children,
+            cluster_id,
+            parent_atlas_id,
+        ))
+    }
+
+    fn can_place(&self, texture: &ClusterBoundingTexture) -> bool {
+        let (scaled_width, scaled_height) = self.scale_dimensions(
+            texture.crop_width,
+            texture.crop_height,
+            texture.downsample_factor.value(),
+        );
+        let width = self.config.footprint(scaled_width);
+        let height = self.config.footprint(scaled_height);
+        self.find_waste_rect(width, height).is_some()
+            || self
+                .config
+                .orientations(width, height)
+                .any(|(width, height, _)| {
+                    (0..self.skyline.len())
+                        .any(|index| self.fits_at(index, width, height).is_some())
+                })
+    }
+
+    fn free_texture(&mut self, placed: &PlacedTextureGeometry) {
+        let rect = self.config.occupied_rect(placed);
+        if let Some(index) = self.used_rects.iter().position(|used| *used == rect) {
+            self.used_rects.swap_remove(index);
+            self.waste_rects.push(rect);
+        }
+    }
+
+    fn occupy_texture(&mut self, placed: &PlacedTextureGeometry) {
+        let rect = self.config.occupied_rect(placed);
+        if self.used_rects.contains(&rect) {
+            return;
+        }
+        self.waste_rects = self
+            .waste_rects
+            .iter()
+            .flat_map(|waste_rect| subtract_rect(waste_rect, &rect))
+            .collect();
+        self.raise_skyline(rect);
+        self.used_rects.push(rect);
+    }
+
+    fn reset_param(&mut self) {
+        let initial_node = SkylineNode {
+            x: 0,
+            y: 0,
+            width: self.config.width,
+        };
+        self.skyline = vec![initial_node];
+        self.waste_rects.clear();
+        self.used_rects.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::place::tests::{assert_no_overlaps, bounding_texture};
+
+    fn place_all(
+        placer: &mut SkylineTexturePlacer,
+        sizes: &[(u32, u32)],
+    ) -> Vec<PlacedTextureGeometry> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &(width, height))| {
+                let texture = bounding_texture(width, height);
+                assert!(placer.can_place(&texture));
+                placer
+                    .place_texture(texture, vec![], i.to_string(), 0)
+                    .unwrap()
+                    .0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_skyline_fills_atlas() {
+        let config = TexturePlacerConfig::new(64, 64, 0);
+        let mut placer = SkylineTexturePlacer::new(config, false);
+
+        let placed = place_all(&mut placer, &[(32, 48), (32, 48), (32, 16), (32, 16)]);
+        assert!(!placer.can_place(&bounding_texture(1, 1)));
+        assert_no_overlaps(&placed, 64, 64);
+    }
+
+    #[test]
+    fn test_skyline_reuses_waste() {
+        let sizes = [(32, 32), (64, 16)];
+
+        let mut placer = SkylineTexturePlacer::new(TexturePlacerConfig::new(64, 64, 0), false);
+        place_all(&mut placer, &sizes);
+        assert!(!placer.can_place(&bounding_texture(32, 32)));
+
+        // The gap to the right of the first texture is below the skyline of the second one
+        let mut placer = SkylineTexturePlacer::new(TexturePlacerConfig::new(64, 64, 0), true);
+        let mut placed = place_all(&mut placer, &sizes);
+        placed.extend(place_all(&mut placer, &[(32, 32)]));
+        assert_eq!(placed[2].origin, (32, 0));
+        assert_no_overlaps(&placed, 64, 64);
+    }
+
+    #[test]
+    fn test_skyline_frees_once() {
+        let mut placer = SkylineTexturePlacer::new(TexturePlacerConfig::new(64, 64, 0), false);
+        let mut placed = place_all(&mut placer, &[(32, 32)]);
+        let freed = placed.pop().unwrap();
+
+        // Freeing the same placement again, or one that was never made, is ignored
+        placer.free_texture(&freed);
+        placer.free_texture(&freed);
+        placer.free_texture(&PlacedTextureGeometry {
+            origin: (32, 32),
+            ..freed.clone()
+        });
+        let placed = place_all(&mut placer, &[(32, 32), (32, 32)]);
+        assert_eq!(placed[0].origin, freed.origin);
+        assert_ne!(placed[1].origin, freed.origin);
+        assert_no_overlaps(&placed, 64, 64);
+    }
+}
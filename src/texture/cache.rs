@@ -1,14 +1,13 @@
-use std::path::PathBuf;
-
 use image::DynamicImage;
 use stretto::Cache;
 use sys_info::mem_info;
 
-use super::utils::get_image_size;
+use super::{TextureSource, TextureSourceId};
+use crate::error::Result;
 
 // Cache for storing the only size of the image
 pub struct TextureSizeCache {
-    cache: Cache<PathBuf, (u32, u32)>,
+    cache: Cache<TextureSourceId, (u32, u32)>,
 }
 
 impl TextureSizeCache {
@@ -18,17 +17,17 @@ impl TextureSizeCache {
         }
     }
 
-    pub fn get_or_insert(&self, image_path: &PathBuf) -> (u32, u32) {
-        match self.cache.get(image_path) {
-            Some(size) => *size.value(),
+    pub fn get_or_insert(&self, source: &TextureSource) -> Result<(u32, u32)> {
+        match self.cache.get(source.id()) {
+            Some(size) => Ok(*size.value()),
             None => {
-                let size = get_image_size(image_path).unwrap();
+                let size = source.dimensions()?;
                 // Since it only retains the size of the texture, set the cost to 1 for everything.
                 let cost = 1;
-                self.cache.insert(image_path.to_path_buf(), size, cost);
-                self.cache.wait().unwrap();
+                self.cache.insert(source.id().clone(), size, cost);
+                self.cache.wait()?;
 
-                size
+                Ok(size)
             }
         }
     }
@@ -48,15 +47,14 @@ impl Drop for TextureSizeCache {
 
 // Cache for storing the image
 pub struct TextureCache {
-    cache: Cache<PathBuf, DynamicImage>,
+    cache: Cache<TextureSourceId, DynamicImage>,
 }
 
 impl TextureCache {
     pub fn new(capacity: usize) -> Self {
-        let default_capacity = get_cache_size().unwrap();
         if capacity == 0 {
             TextureCache {
-                cache: Cache::new(default_capacity, 2_000_000_000).unwrap(),
+                cache: Cache::new(get_cache_size(), 2_000_000_000).unwrap(),
             }
         } else {
             TextureCache {
@@ -65,23 +63,23 @@ impl TextureCache {
         }
     }
 
-    pub fn get_image(&self, path: &PathBuf) -> DynamicImage {
-        match self.cache.get(path) {
-            Some(image) => image.value().clone(),
+    pub fn get_image(&self, source: &TextureSource) -> Result<DynamicImage> {
+        match self.cache.get(source.id()) {
+            Some(image) => Ok(image.value().clone()),
             None => {
-                let image = image::open(path).expect("Failed to open image file");
+                let image = source.load()?;
                 let cost = image.width() * image.height() * image.color().bytes_per_pixel() as u32;
                 self.cache
-                    .insert(path.to_path_buf(), image.clone(), cost as i64);
-                self.cache.wait().unwrap();
+                    .insert(source.id().clone(), image.clone(), cost as i64);
+                self.cache.wait()?;
 
-                image
+                Ok(image)
             }
         }
     }
 }
 
-fn get_cache_size() -> Result<usize, String> {
+fn get_cache_size() -> usize {
     const MIN_CACHE_SIZE: usize = 100 * 1024 * 1024; // 100MB
     const MAX_CACHE_SIZE: usize = 2 * 1024 * 1024 * 1024; // 2GB
 
@@ -90,10 +88,11 @@ fn get_cache_size() -> Result<usize, String> {
             let total_memory = mem.total as usize * 1024;
             // 15% of total memory
             let cache_size = (total_memory as f64 * 0.15) as usize;
-            Ok(cache_size.clamp(MIN_CACHE_SIZE, MAX_CACHE_SIZE))
+            cache_size.clamp(MIN_CACHE_SIZE, MAX_CACHE_SIZE)
         }
 
-        Err(e) => Err(format!("Failed to retrieve memory information.: {}", e)),
+        // Fall back to the smallest cache if the memory information is not available
+        Err(_) => MIN_CACHE_SIZE,
     }
 }
 
@@ -102,3 +101,26 @@ impl Drop for TextureCache {
         self.cache.close().unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AtlasPackerError;
+
+    #[test]
+    fn test_missing_image() {
+        let path = TextureSource::path("does/not/exist.png");
+
+        let texture_cache = TextureCache::new(1_000_000);
+        assert!(matches!(
+            texture_cache.get_image(&path),
+            Err(AtlasPackerError::ImageNotFound { .. })
+        ));
+
+        let texture_size_cache = TextureSizeCache::new();
+        assert!(matches!(
+            texture_size_cache.get_or_insert(&path),
+            Err(AtlasPackerError::ImageNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,159 @@
+use image::{Rgba, RgbaImage};
+use rayon::prelude::*;
+
+use super::utils::{distance_to_polygon, is_point_inside_polygon};
+
+/// Settings for masking out the pixels of a cropped texture that are not covered by its polygons
+#[derive(Debug, Clone)]
+pub struct PolygonMask {
+    /// Pixels within this distance (in source pixels) of a polygon are treated as covered,
+    /// so that bilinear sampling at the polygon edges does not pick up the fill colour
+    pub dilation: f64,
+    /// Number of sub-samples per axis used to compute the coverage of a pixel.
+    /// 1 gives a hard edge, larger values give an anti-aliased edge.
+    pub samples: u32,
+    /// Colour of the pixels outside every polygon
+    pub fill: Rgba<u8>,
+}
+
+impl Default for PolygonMask {
+    fn default() -> Self {
+        PolygonMask {
+            dilation: 1.0,
+            samples: 4,
+            fill: Rgba([0, 0, 0, 0]),
+        }
+    }
+}
+
+impl PolygonMask {
+    /// Mask `image`, whose top-left corner is at `origin` in the coordinate system of `polygons`
+    pub(super) fn apply(
+        &self,
+        image: &mut RgbaImage,
+        origin: (f64, f64),
+        polygons: &[Vec<(f64, f64)>],
+    ) {
+        let samples = self.samples.max(1);
+        let dilation = self.dilation.max(0.0);
+        // Bounding boxes (expanded by the dilation) to skip polygons quickly
+        let bounds = polygons
+            .iter()
+            .map(|polygon| {
+                polygon.iter().fold(
+                    (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+                    |(min_x, min_y, max_x, max_y), &(x, y)| {
+                        (
+                            min_x.min(x - dilation),
+                            min_y.min(y - dilation),
+                            max_x.max(x + dilation),
+                            max_y.max(y + dilation),
+                        )
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let is_covered = |point: (f64, f64)| {
+            polygons.iter().zip(&bounds).any(|(polygon, bound)| {
+                if point.0 < bound.0 || point.1 < bound.1 || point.0 > bound.2 || point.1 > bound.3
+                {
+                    return false;
+                }
+                (polygon.len() >= 3 && is_point_inside_polygon(point, polygon))
+                    || (dilation > 0.0 && distance_to_polygon(point, polygon) <= dilation)
+            })
+        };
+
+        let row_length = image.width() as usize * 4;
+        if row_length == 0 {
+            return;
+        }
+        let buffer: &mut [u8] = image;
+        buffer
+            .par_chunks_mut(row_length)
+            .enumerate()
+            .for_each(|(py, row)| {
+                for (px, pixel) in row.chunks_exact_mut(4).enumerate() {
+                    let mut covered = 0;
+                    for sy in 0..samples {
+                        for sx in 0..samples {
+                            let x = origin.0 + px as f64 + (sx as f64 + 0.5) / samples as f64;
+                            let y = origin.1 + py as f64 + (sy as f64 + 0.5) / samples as f64;
+                            if is_covered((x, y)) {
+                                covered += 1;
+                            }
+                        }
+                    }
+                    let coverage = covered as f64 / (samples * samples) as f64;
+                    if coverage < 1.0 {
+                        self.blend(pixel, coverage);
+                    }
+                }
+            });
+    }
+
+    // Composite the pixel, weighted by its coverage, over the fill colour
+    fn blend(&self, pixel: &mut [u8], coverage: f64) {
+        let source_alpha = pixel[3] as f64 / 255.0 * coverage;
+        let fill_alpha = self.fill[3] as f64 / 255.0 * (1.0 - coverage);
+        let alpha = source_alpha + fill_alpha;
+
+        for (channel, fill) in pixel.iter_mut().zip(self.fill.0).take(3) {
+            *channel = if alpha > 0.0 {
+                ((*channel as f64 * source_alpha + fill as f64 * fill_alpha) / alpha).round() as u8
+            } else {
+                fill
+            };
+        }
+        pixel[3] = (alpha * 255.0).round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Opaque white image masked by the lower-left half triangle
+    fn masked(mask: &PolygonMask) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 255]));
+        let triangle = vec![(0.0, 0.0), (8.0, 8.0), (0.0, 8.0)];
+        mask.apply(&mut image, (0.0, 0.0), &[triangle]);
+        image
+    }
+
+    #[test]
+    fn test_hard_mask() {
+        let image = masked(&PolygonMask {
+            dilation: 0.0,
+            samples: 1,
+            fill: Rgba([255, 0, 0, 255]),
+        });
+        assert_eq!(image.get_pixel(0, 7), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(7, 0), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_antialiased_mask() {
+        let image = masked(&PolygonMask {
+            dilation: 0.0,
+            samples: 4,
+            ..Default::default()
+        });
+        assert_eq!(image.get_pixel(0, 7)[3], 255);
+        assert_eq!(image.get_pixel(7, 0)[3], 0);
+        // Pixels on the diagonal are partially covered but keep their colour
+        let edge = image.get_pixel(3, 3);
+        assert!(edge[3] > 0 && edge[3] < 255);
+        assert_eq!(edge[0], 255);
+
+        // The dilation keeps the pixels next to the polygon
+        let image = masked(&PolygonMask {
+            dilation: 1.5,
+            samples: 4,
+            ..Default::default()
+        });
+        assert_eq!(image.get_pixel(4, 3)[3], 255);
+        assert_eq!(image.get_pixel(7, 0)[3], 0);
+    }
+}
@@ -1,20 +1,27 @@
-use std::path::{Path, PathBuf};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use utils::{calc_bbox, polygons_overlap, uv_to_pixel_coords};
 
-use image::{DynamicImage, GenericImageView, ImageBuffer};
-use utils::{calc_bbox, uv_to_pixel_coords};
+use crate::error::{AtlasPackerError, Result};
 
 pub mod cache;
+mod mask;
+mod source;
 mod utils;
 
+pub use mask::PolygonMask;
+pub use source::{TextureSource, TextureSourceId};
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "f32"))]
 pub struct DownsampleFactor(f32);
 
 impl DownsampleFactor {
-    pub fn new(factor: &f32) -> Self {
+    pub fn new(factor: &f32) -> Result<Self> {
         if (0.0..=1.0).contains(factor) {
-            DownsampleFactor(*factor)
+            Ok(DownsampleFactor(*factor))
         } else {
-            panic!("The argument must be entered between 0~1.") //FIXME: panic! is not recommended
+            Err(AtlasPackerError::InvalidDownsampleFactor(*factor))
         }
     }
 
@@ -23,19 +30,28 @@ impl DownsampleFactor {
     }
 }
 
+impl TryFrom<f32> for DownsampleFactor {
+    type Error = AtlasPackerError;
+
+    fn try_from(factor: f32) -> Result<Self> {
+        DownsampleFactor::new(&factor)
+    }
+}
+
 /// Texture image mapped to a polygon
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PolygonMappedTexture {
     // texture
-    pub image_path: PathBuf,
+    pub source: TextureSource,
     pub downsample_factor: DownsampleFactor,
-    // polygon
-    pub pixel_coords: Vec<(u32, u32)>,
+    // polygon, in sub-pixel accurate coordinates (top-left origin)
+    pub pixel_coords: Vec<(f64, f64)>,
 }
 
 impl PolygonMappedTexture {
     pub fn new(
-        image_path: &Path,
+        source: impl Into<TextureSource>,
         size: (u32, u32),
         uv_coords: &[(f64, f64)],
         downsample_factor: DownsampleFactor,
@@ -43,20 +59,20 @@ impl PolygonMappedTexture {
         let pixel_coords = uv_to_pixel_coords(uv_coords, size.0, size.1);
 
         PolygonMappedTexture {
-            image_path: image_path.to_path_buf(),
+            source: source.into(),
             downsample_factor,
             pixel_coords,
         }
     }
 
     #[inline]
-    pub fn bbox(&self) -> (u32, u32, u32, u32) {
+    pub fn bbox(&self) -> (f64, f64, f64, f64) {
         calc_bbox(&self.pixel_coords)
     }
 
     #[allow(dead_code)]
     pub fn bbox_overlaps(&self, other: &Self) -> bool {
-        if self.image_path != other.image_path {
+        if self.source.id() != other.source.id() {
             return false;
         }
 
@@ -66,6 +82,12 @@ impl PolygonMappedTexture {
         !(max_x_0 < min_x_1 || max_x_1 < min_x_0 || max_y_0 < min_y_1 || max_y_1 < min_y_0)
     }
 
+    /// Whether the polygons share the image and overlap, or come within `tolerance` pixels of each other
+    pub fn polygon_overlaps(&self, other: &Self, tolerance: f64) -> bool {
+        self.source.id() == other.source.id()
+            && polygons_overlap(&self.pixel_coords, &other.pixel_coords, tolerance)
+    }
+
     pub fn get_cropped_uv_coords(
         &self,
         x: u32,
@@ -77,8 +99,8 @@ impl PolygonMappedTexture {
             .iter()
             .map(|(px, py)| {
                 (
-                    (*px - x) as f64 / width as f64,
-                    1.0 - (*py - y) as f64 / height as f64,
+                    (px - x as f64) / width as f64,
+                    1.0 - (py - y as f64) / height as f64,
                 )
             })
             .collect()
@@ -86,33 +108,37 @@ impl PolygonMappedTexture {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClusterBoundingTexture {
-    pub image_path: PathBuf,
+    pub source: TextureSource,
     // The origin of the cropped image in the original image (top-left corner).
     crop_origin: (u32, u32),
     pub crop_width: u32,
     pub crop_height: u32,
     pub downsample_factor: DownsampleFactor,
+    // Pixel coordinates of the member polygons in the original image, used for masking.
+    polygons: Vec<Vec<(f64, f64)>>,
 }
 
 impl ClusterBoundingTexture {
     pub fn new(texture: &PolygonMappedTexture) -> Self {
-        let bounding_box = calc_bbox(&texture.pixel_coords);
+        let (min_x, min_y, max_x, max_y) = pixel_envelope(texture);
         Self {
-            image_path: texture.image_path.clone(),
-            crop_origin: (bounding_box.0, bounding_box.1),
-            crop_width: bounding_box.2 - bounding_box.0,
-            crop_height: bounding_box.3 - bounding_box.1,
+            source: texture.source.clone(),
+            crop_origin: (min_x, min_y),
+            crop_width: max_x - min_x,
+            crop_height: max_y - min_y,
             downsample_factor: texture.downsample_factor.clone(),
+            polygons: vec![texture.pixel_coords.clone()],
         }
     }
 
     pub fn expand(&self, texture: &PolygonMappedTexture) -> Option<Self> {
-        if self.image_path != texture.image_path {
+        if self.source.id() != texture.source.id() {
             return None;
         }
 
-        let (min_x_0, min_y_0, max_x_0, max_y_0) = calc_bbox(&texture.pixel_coords);
+        let (min_x_0, min_y_0, max_x_0, max_y_0) = pixel_envelope(texture);
 
         let (min_x_1, min_y_1, max_x_1, max_y_1) = (
             self.crop_origin.0,
@@ -124,20 +150,34 @@ impl ClusterBoundingTexture {
         let (min_x_new, min_y_new) = (min_x_0.min(min_x_1), min_y_0.min(min_y_1));
         let (max_x_new, max_y_new) = (max_x_0.max(max_x_1), max_y_0.max(max_y_1));
 
+        let mut polygons = self.polygons.clone();
+        polygons.push(texture.pixel_coords.clone());
+
         Some(Self {
-            image_path: texture.image_path.clone(),
+            source: texture.source.clone(),
             crop_origin: (min_x_new, min_y_new),
             crop_width: max_x_new - min_x_new,
             crop_height: max_y_new - min_y_new,
-            downsample_factor: DownsampleFactor::new(
-                &self
-                    .downsample_factor
+            downsample_factor: DownsampleFactor(
+                self.downsample_factor
                     .value()
                     .max(texture.downsample_factor.value()),
             ),
+            polygons,
         })
     }
 
+    /// Top-left corner of the crop in the original image
+    pub fn crop_origin(&self) -> (u32, u32) {
+        self.crop_origin
+    }
+
+    // Stop masking in the member polygon at `index`, once it has been removed from the cluster.
+    // The crop is left as it is, since the cluster may already be placed.
+    pub(crate) fn remove_polygon(&mut self, index: usize) {
+        self.polygons.remove(index);
+    }
+
     pub fn get_child(&self, texture: &PolygonMappedTexture) -> ChildUVPolygon {
         let cropped_uv_coords = texture.get_cropped_uv_coords(
             self.crop_origin.0,
@@ -148,80 +188,34 @@ impl ClusterBoundingTexture {
         ChildUVPolygon { cropped_uv_coords }
     }
 
-    pub fn crop(&self, image: &DynamicImage) -> DynamicImage {
-        let cropped_image = image
-            .view(
-                self.crop_origin.0,
-                self.crop_origin.1,
-                self.crop_width,
-                self.crop_height,
-            )
-            .to_image();
-
-        // Collect pixels into a Vec and then process in parallel
-        let pixels: Vec<_> = cropped_image.enumerate_pixels().collect();
-
-        // TODO: Crop pixels that are not contained in the polygon
-        /*
-        let samples = 1;
-        let num_threads = rayon::current_num_threads();
-        let chunk_size = (pixels.len() / num_threads).clamp(1, pixels.len() + 1);
-
-        let (sender, receiver) = mpsc::channel();
-
-        // If the center coordinates of the pixel are contained within a polygon composed of UV coordinates, the pixel is written
-        pixels
-            .par_chunks(chunk_size)
-            .for_each_with(sender, |s, chunk| {
-                let mut local_results = Vec::new();
-
-                for &(px, py, pixel) in chunk {
-                    let mut is_inside = false;
-
-                    'subpixels: for sx in 0..samples {
-                        for sy in 0..samples {
-                            let x = (px as f64 + (sx as f64 + 0.5) / samples as f64)
-                                / self.width() as f64;
-                            let y = 1.0
-                                - (py as f64 + (sy as f64 + 0.5) / samples as f64)
-                                    / self.height() as f64;
-                            // Adjust x and y to the center of the pixel
-                            let center_x = x + 0.5 / self.width() as f64;
-                            let center_y = y - 0.5 / self.height() as f64;
-
-                            if is_point_inside_polygon(
-                                (center_x, center_y),
-                                &self.cropped_uv_coords,
-                            ) {
-                                is_inside = true;
-                                break 'subpixels;
-                            }
-                        }
-                    }
-
-                    if is_inside {
-                        local_results.push((px, py, *pixel));
-                    } else {
-                        // FIXME: Do not crop temporarily because pixel boundary jaggies will occur.
-                        local_results.push((px, py, *pixel));
-                    }
-                }
-
-                s.send(local_results).unwrap();
-            });
-
-
-        // Collect results in the main thread
-        let mut clipped = ImageBuffer::new(self.width(), self.height());
-        for received in receiver {
-            for (px, py, pixel) in received {
-                clipped.put_pixel(px, py, pixel);
-            }
-        }
-        */
-        let mut clipped = ImageBuffer::new(self.crop_width, self.crop_height);
-        for &(px, py, pixel) in &pixels {
-            clipped.put_pixel(px, py, *pixel);
+    /// Crop the bounding area from the original image and downsample it.
+    /// The image is tiled where the area extends beyond it, as with repeating UVs.
+    /// If `mask` is given, the pixels that are not covered by any member polygon are masked out.
+    pub fn crop(&self, image: &DynamicImage, mask: Option<&PolygonMask>) -> DynamicImage {
+        let mut clipped = if self.crop_origin.0 + self.crop_width <= image.width()
+            && self.crop_origin.1 + self.crop_height <= image.height()
+        {
+            image
+                .view(
+                    self.crop_origin.0,
+                    self.crop_origin.1,
+                    self.crop_width,
+                    self.crop_height,
+                )
+                .to_image()
+        } else {
+            let (origin_x, origin_y) = self.crop_origin;
+            RgbaImage::from_fn(self.crop_width, self.crop_height, |x, y| {
+                image.get_pixel(
+                    (origin_x + x) % image.width(),
+                    (origin_y + y) % image.height(),
+                )
+            })
+        };
+
+        if let Some(mask) = mask {
+            let origin = (self.crop_origin.0 as f64, self.crop_origin.1 as f64);
+            mask.apply(&mut clipped, origin, &self.polygons);
         }
 
         // Downsample
@@ -237,8 +231,93 @@ impl ClusterBoundingTexture {
     }
 }
 
+// The smallest whole-texel rectangle containing the polygon, so that no texel it touches is cut off
+fn pixel_envelope(texture: &PolygonMappedTexture) -> (u32, u32, u32, u32) {
+    let (min_x, min_y, max_x, max_y) = texture.bbox();
+    (
+        min_x.floor() as u32,
+        min_y.floor() as u32,
+        max_x.ceil() as u32,
+        max_y.ceil() as u32,
+    )
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChildUVPolygon {
     // UV coordinates for the bounding texture (bottom-left origin).
     pub cropped_uv_coords: Vec<(f64, f64)>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_repeating_uv() {
+        // A 4x4 image with a distinct value in every pixel
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, y| {
+            Rgba([(y * 4 + x) as u8, 0, 0, 255])
+        }));
+        let factor = DownsampleFactor::new(&1.0).unwrap();
+
+        // Repeats the image twice in both directions, starting one tile to the left
+        let uv_coords = [(-1.0, 0.0), (1.0, 0.0), (1.0, 2.0), (-1.0, 2.0)];
+        let texture = PolygonMappedTexture::new(Path::new("a.png"), (4, 4), &uv_coords, factor);
+        assert_eq!(
+            texture.pixel_coords,
+            [(0.0, 8.0), (8.0, 8.0), (8.0, 0.0), (0.0, 0.0)]
+        );
+
+        let cluster = ClusterBoundingTexture::new(&texture);
+        let cropped = cluster.crop(&image, None);
+        assert_eq!(cropped.dimensions(), (8, 8));
+        for (x, y) in [(0, 0), (5, 2), (3, 7), (7, 7)] {
+            assert_eq!(cropped.get_pixel(x, y), image.get_pixel(x % 4, y % 4));
+        }
+
+        // The cropped UVs span the whole tiled crop
+        let child = cluster.get_child(&texture);
+        assert_eq!(
+            child.cropped_uv_coords,
+            [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+        );
+
+        // UVs within [0, 1] stay within the image
+        let texture = PolygonMappedTexture::new(
+            Path::new("a.png"),
+            (4, 4),
+            &[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0)],
+            DownsampleFactor::new(&1.0).unwrap(),
+        );
+        assert_eq!(texture.pixel_coords, [(0.0, 4.0), (4.0, 4.0), (4.0, 0.0)]);
+    }
+
+    #[test]
+    fn test_sub_pixel_uv() {
+        let uv_coords = [(0.3, 0.1), (0.9, 0.1), (0.9, 0.55)];
+        let texture = PolygonMappedTexture::new(
+            Path::new("a.png"),
+            (10, 10),
+            &uv_coords,
+            DownsampleFactor::new(&1.0).unwrap(),
+        );
+
+        // The crop is the whole-texel envelope of [3, 9] x [4.5, 9]
+        let cluster = ClusterBoundingTexture::new(&texture);
+        assert_eq!(cluster.crop_origin, (3, 4));
+        assert_eq!((cluster.crop_width, cluster.crop_height), (6, 5));
+
+        // The cropped UVs point at the same positions within the texels as the original UVs
+        let child = cluster.get_child(&texture);
+        for ((u, v), (cropped_u, cropped_v)) in uv_coords.iter().zip(child.cropped_uv_coords) {
+            let x = 3.0 + cropped_u * 6.0;
+            let y = 4.0 + (1.0 - cropped_v) * 5.0;
+            assert!((x - u * 10.0).abs() < 1e-9);
+            assert!((y - (1.0 - v) * 10.0).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use image::{DynamicImage, ImageReader};
+
+use super::utils::get_image_size;
+use crate::error::{AtlasPackerError, Result};
+
+/// Identifies a texture image. Polygons are clustered, and images are cached, by this ID.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureSourceId(Arc<str>);
+
+impl TextureSourceId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for TextureSourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for TextureSourceId {
+    fn from(id: &str) -> Self {
+        TextureSourceId(id.into())
+    }
+}
+
+impl From<String> for TextureSourceId {
+    fn from(id: String) -> Self {
+        TextureSourceId(id.into())
+    }
+}
+
+type LoadImage = dyn Fn() -> Result<DynamicImage> + Send + Sync;
+
+#[derive(Clone)]
+enum SourceData {
+    Path(PathBuf),
+    Bytes(Arc<[u8]>),
+    Image(Arc<DynamicImage>),
+    Callback(Arc<LoadImage>),
+    // Deserialized from a source other than a file, whose data has to be attached again
+    #[cfg_attr(not(feature = "serde"), allow(dead_code))]
+    Detached,
+}
+
+/// Where the pixels of a texture come from.
+/// Sources with the same ID are assumed to hold the same image.
+#[derive(Clone)]
+pub struct TextureSource {
+    id: TextureSourceId,
+    data: SourceData,
+}
+
+impl TextureSource {
+    /// Image file on disk, identified by its path
+    pub fn path(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        TextureSource {
+            id: path.to_string_lossy().into_owned().into(),
+            data: SourceData::Path(path.to_path_buf()),
+        }
+    }
+
+    /// Encoded image file in memory, such as a buffer view of a GLB or an entry of a zip archive
+    pub fn bytes(id: impl Into<TextureSourceId>, bytes: impl Into<Arc<[u8]>>) -> Self {
+        TextureSource {
+            id: id.into(),
+            data: SourceData::Bytes(bytes.into()),
+        }
+    }
+
+    /// Image that has already been decoded
+    pub fn image(id: impl Into<TextureSourceId>, image: impl Into<Arc<DynamicImage>>) -> Self {
+        TextureSource {
+            id: id.into(),
+            data: SourceData::Image(image.into()),
+        }
+    }
+
+    /// Image produced on demand by `load`, which is called whenever the image is not cached
+    pub fn callback(
+        id: impl Into<TextureSourceId>,
+        load: impl Fn() -> Result<DynamicImage> + Send + Sync + 'static,
+    ) -> Self {
+        TextureSource {
+            id: id.into(),
+            data: SourceData::Callback(Arc::new(load)),
+        }
+    }
+
+    pub fn id(&self) -> &TextureSourceId {
+        &self.id
+    }
+
+    pub fn load(&self) -> Result<DynamicImage> {
+        match &self.data {
+            SourceData::Path(path) => {
+                image::open(path).map_err(|e| AtlasPackerError::from_image_error(path, e))
+            }
+            SourceData::Bytes(bytes) => {
+                image::load_from_memory(bytes).map_err(|e| self.decode_error(e))
+            }
+            SourceData::Image(image) => Ok(image.as_ref().clone()),
+            SourceData::Callback(load) => load(),
+            SourceData::Detached => Err(AtlasPackerError::SourceDetached(self.id.clone())),
+        }
+    }
+
+    /// Width and height of the image, read from the header where possible
+    pub fn dimensions(&self) -> Result<(u32, u32)> {
+        match &self.data {
+            SourceData::Path(path) => {
+                get_image_size(path).map_err(|e| AtlasPackerError::from_image_error(path, e))
+            }
+            SourceData::Bytes(bytes) => ImageReader::new(Cursor::new(bytes.as_ref()))
+                .with_guessed_format()
+                .map_err(AtlasPackerError::from)?
+                .into_dimensions()
+                .map_err(|e| self.decode_error(e)),
+            SourceData::Image(image) => Ok((image.width(), image.height())),
+            SourceData::Callback(load) => load().map(|image| (image.width(), image.height())),
+            SourceData::Detached => Err(AtlasPackerError::SourceDetached(self.id.clone())),
+        }
+    }
+
+    fn decode_error(&self, source: image::ImageError) -> AtlasPackerError {
+        AtlasPackerError::ImageDecode {
+            texture: self.id.clone(),
+            source,
+        }
+    }
+}
+
+impl fmt::Debug for TextureSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match &self.data {
+            SourceData::Path(_) => "Path",
+            SourceData::Bytes(_) => "Bytes",
+            SourceData::Image(_) => "Image",
+            SourceData::Callback(_) => "Callback",
+            SourceData::Detached => "Detached",
+        };
+        f.debug_struct("TextureSource")
+            .field("id", &self.id)
+            .field("kind", &kind)
+            .finish()
+    }
+}
+
+impl From<&Path> for TextureSource {
+    fn from(path: &Path) -> Self {
+        TextureSource::path(path)
+    }
+}
+
+impl From<&PathBuf> for TextureSource {
+    fn from(path: &PathBuf) -> Self {
+        TextureSource::path(path)
+    }
+}
+
+impl From<PathBuf> for TextureSource {
+    fn from(path: PathBuf) -> Self {
+        TextureSource::path(path)
+    }
+}
+
+// Only file paths survive serialization. The other sources keep their IDs,
+// and their data has to be attached again with `PackedAtlasProvider::attach_source`.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SerializedSource {
+    id: String,
+    path: Option<PathBuf>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for TextureSource {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let path = match &self.data {
+            SourceData::Path(path) => Some(path.clone()),
+            _ => None,
+        };
+        SerializedSource {
+            id: self.id.to_string(),
+            path,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for TextureSource {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let SerializedSource { id, path } = SerializedSource::deserialize(deserializer)?;
+        let data = match path {
+            Some(path) => SourceData::Path(path),
+            None => SourceData::Detached,
+        };
+        Ok(TextureSource {
+            id: id.into(),
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageFormat, Rgba, RgbaImage};
+
+    use super::*;
+
+    #[test]
+    fn test_texture_sources() {
+        let image = RgbaImage::from_pixel(3, 2, Rgba([1, 2, 3, 255]));
+        let mut png = Cursor::new(Vec::new());
+        image.write_to(&mut png, ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+
+        let returned = DynamicImage::ImageRgba8(image.clone());
+        let sources = [
+            TextureSource::bytes("bytes", png.clone()),
+            TextureSource::image("image", DynamicImage::ImageRgba8(image.clone())),
+            TextureSource::callback("callback", move || Ok(returned.clone())),
+        ];
+        for source in sources {
+            assert_eq!(source.dimensions().unwrap(), (3, 2), "{:?}", source);
+            assert_eq!(source.load().unwrap().to_rgba8(), image, "{:?}", source);
+        }
+
+        let source = TextureSource::bytes("broken", &png[..16]);
+        assert!(matches!(
+            source.load(),
+            Err(AtlasPackerError::ImageDecode { texture, .. }) if texture.as_str() == "broken"
+        ));
+
+        assert_eq!(
+            TextureSource::path("textures/a.png").id(),
+            &TextureSourceId::from("textures/a.png")
+        );
+    }
+}
@@ -2,7 +2,6 @@ use std::path::Path;
 
 use image::ImageReader;
 
-#[allow(dead_code)]
 pub fn is_point_inside_polygon(test_point: (f64, f64), polygon: &[(f64, f64)]) -> bool {
     let mut is_inside = false;
     let mut previous_vertex_index = polygon.len() - 1;
@@ -26,28 +25,134 @@ pub fn is_point_inside_polygon(test_point: (f64, f64), polygon: &[(f64, f64)]) -
     is_inside
 }
 
+// Distance from the point to the closest edge of the polygon
+pub fn distance_to_polygon(test_point: (f64, f64), polygon: &[(f64, f64)]) -> f64 {
+    let mut min_distance = f64::MAX;
+    let mut previous_vertex_index = polygon.len().saturating_sub(1);
+
+    for current_vertex_index in 0..polygon.len() {
+        let (start_x, start_y) = polygon[previous_vertex_index];
+        let (end_x, end_y) = polygon[current_vertex_index];
+
+        // Project the point onto the edge, clamped to the edge's end points
+        let (edge_x, edge_y) = (end_x - start_x, end_y - start_y);
+        let length_squared = edge_x * edge_x + edge_y * edge_y;
+        let t = if length_squared > 0.0 {
+            (((test_point.0 - start_x) * edge_x + (test_point.1 - start_y) * edge_y)
+                / length_squared)
+                .clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let (closest_x, closest_y) = (start_x + t * edge_x, start_y + t * edge_y);
+        let distance = (test_point.0 - closest_x).hypot(test_point.1 - closest_y);
+        min_distance = min_distance.min(distance);
+
+        previous_vertex_index = current_vertex_index;
+    }
+
+    min_distance
+}
+
+// Whether the polygons overlap, or come within `tolerance` of each other
+pub fn polygons_overlap(
+    polygon_0: &[(f64, f64)],
+    polygon_1: &[(f64, f64)],
+    tolerance: f64,
+) -> bool {
+    if polygon_0.is_empty() || polygon_1.is_empty() {
+        return false;
+    }
+
+    // One polygon is inside the other
+    if is_point_inside_polygon(polygon_0[0], polygon_1)
+        || is_point_inside_polygon(polygon_1[0], polygon_0)
+    {
+        return true;
+    }
+
+    // Two edges are either crossing, or closest at one of their end points
+    let edges_cross = edges(polygon_0)
+        .any(|edge_0| edges(polygon_1).any(|edge_1| segments_cross(edge_0, edge_1)));
+    edges_cross
+        || polygon_0
+            .iter()
+            .any(|&point| distance_to_polygon(point, polygon_1) <= tolerance)
+        || polygon_1
+            .iter()
+            .any(|&point| distance_to_polygon(point, polygon_0) <= tolerance)
+}
+
+type Segment = ((f64, f64), (f64, f64));
+
+fn edges(polygon: &[(f64, f64)]) -> impl Iterator<Item = Segment> + '_ {
+    let previous_vertices = polygon.iter().cycle().skip(polygon.len() - 1);
+    previous_vertices
+        .zip(polygon)
+        .map(|(&start, &end)| (start, end))
+}
+
+// Whether the segments cross at a single point in both of their interiors
+fn segments_cross((a, b): Segment, (c, d): Segment) -> bool {
+    let cross = |o: (f64, f64), p: (f64, f64), q: (f64, f64)| {
+        (p.0 - o.0) * (q.1 - o.1) - (p.1 - o.1) * (q.0 - o.0)
+    };
+    let (d_0, d_1) = (cross(a, b, c), cross(a, b, d));
+    let (d_2, d_3) = (cross(c, d, a), cross(c, d, b));
+    d_0 * d_1 < 0.0 && d_2 * d_3 < 0.0
+}
+
 pub fn get_image_size<P: AsRef<Path>>(file_path: P) -> Result<(u32, u32), image::ImageError> {
     let reader = ImageReader::open(file_path)?;
     let dimensions = reader.into_dimensions()?;
     Ok(dimensions)
 }
 
-pub fn uv_to_pixel_coords(uv_coords: &[(f64, f64)], width: u32, height: u32) -> Vec<(u32, u32)> {
+// UVs this far outside [0, 1] are treated as repeating instead of as rounding errors
+const REPEAT_EPSILON: f64 = 1e-6;
+
+// Whether the polygon repeats (tiles) the texture
+pub fn is_repeating(uv_coords: &[(f64, f64)]) -> bool {
+    uv_coords.iter().any(|(u, v)| {
+        !(-REPEAT_EPSILON..=1.0 + REPEAT_EPSILON).contains(u)
+            || !(-REPEAT_EPSILON..=1.0 + REPEAT_EPSILON).contains(v)
+    })
+}
+
+// Pixel coordinates are continuous: texel (x, y) spans [x, x + 1) x [y, y + 1).
+// Repeating polygons are shifted by whole tiles so that their pixel coordinates are non-negative.
+// Their pixel coordinates may then exceed the image size, pointing into the repeated image.
+pub fn uv_to_pixel_coords(uv_coords: &[(f64, f64)], width: u32, height: u32) -> Vec<(f64, f64)> {
+    if is_repeating(uv_coords) {
+        let min_u = uv_coords.iter().map(|(u, _)| *u).fold(f64::MAX, f64::min);
+        let max_v = uv_coords.iter().map(|(_, v)| *v).fold(f64::MIN, f64::max);
+        let (shift_u, shift_v) = (-min_u.floor(), 1.0 - max_v.ceil());
+        return uv_coords
+            .iter()
+            .map(|(u, v)| {
+                (
+                    ((u + shift_u) * width as f64).max(0.0),
+                    ((1.0 - (v + shift_v)) * height as f64).max(0.0),
+                )
+            })
+            .collect();
+    }
+
     uv_coords
         .iter()
         .map(|(u, v)| {
             (
-                (u.clamp(0.0, 1.0) * width as f64).min(width as f64 - 1.0) as u32,
-                ((1.0 - v.clamp(0.0, 1.0)) * height as f64).min(height as f64 - 1.0) as u32,
+                u.clamp(0.0, 1.0) * width as f64,
+                (1.0 - v.clamp(0.0, 1.0)) * height as f64,
             )
         })
         .collect()
 }
 
 #[inline]
-pub fn calc_bbox(pixel_coords: &[(u32, u32)]) -> (u32, u32, u32, u32) {
+pub fn calc_bbox(pixel_coords: &[(f64, f64)]) -> (f64, f64, f64, f64) {
     pixel_coords.iter().fold(
-        (u32::MAX, u32::MAX, 0, 0),
+        (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
         |(min_x, min_y, max_x, max_y), (x, y)| {
             (min_x.min(*x), min_y.min(*y), max_x.max(*x), max_y.max(*y))
         },